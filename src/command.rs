@@ -0,0 +1,187 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    fmt,
+    io::{self, Read},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Used if a command does not set its own `timeout`.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// How long error output is still read after the command exited.
+/// Programs it started in the background inherit the pipe and may keep it open.
+const STDERR_GRACE: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub enum CommandOutcome {
+    /// Background commands are not waited for.
+    Detached,
+    Exited {
+        status: ExitStatus,
+        stderr: String,
+    },
+    /// The command was killed after running longer than its timeout.
+    TimedOut(Duration),
+    SpawnFailed(io::Error),
+}
+
+impl CommandOutcome {
+    pub fn success(&self) -> bool {
+        match self {
+            CommandOutcome::Detached => true,
+            CommandOutcome::Exited { status, .. } => status.success(),
+            CommandOutcome::TimedOut(_) | CommandOutcome::SpawnFailed(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for CommandOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandOutcome::Detached => write!(f, "running in the background"),
+            CommandOutcome::Exited { status, stderr } if stderr.trim().is_empty() => {
+                write!(f, "{}", status)
+            }
+            CommandOutcome::Exited { status, stderr } => {
+                write!(f, "{}: {}", status, stderr.trim())
+            }
+            CommandOutcome::TimedOut(timeout) => write!(
+                f,
+                "killed after {}s, set background = true for long running commands",
+                timeout.as_secs()
+            ),
+            CommandOutcome::SpawnFailed(err) => write!(f, "could not be started: {}", err),
+        }
+    }
+}
+
+/// Runs a command and waits for it, unless it runs in the background.
+/// Waiting ends after `timeout`, at which point the command is killed.
+pub fn run_command(
+    base: &str,
+    args: &[String],
+    background: bool,
+    timeout: Duration,
+) -> CommandOutcome {
+    let mut command = Command::new(base);
+    command.args(args);
+    execute(command, background, timeout)
+}
+
+/// Like [`run_command`], for commands that need more setup, e.g. environment variables.
+pub fn execute(mut command: Command, background: bool, timeout: Duration) -> CommandOutcome {
+    command.stdin(Stdio::null());
+    if background {
+        return match command.spawn() {
+            Ok(mut child) => {
+                // reap the child once it exits, so it does not linger as a zombie
+                thread::spawn(move || child.wait());
+                CommandOutcome::Detached
+            }
+            Err(err) => CommandOutcome::SpawnFailed(err),
+        };
+    }
+    let mut child = match command.stderr(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(err) => return CommandOutcome::SpawnFailed(err),
+    };
+    // the reader passes on what it read, so it never has to be joined
+    let (sender, chunks) = mpsc::channel();
+    if let Some(mut stderr) = child.stderr.take() {
+        thread::spawn(move || {
+            let mut buf = [0; 4096];
+            while let Ok(n @ 1..) = stderr.read(&mut buf) {
+                if sender.send(buf[..n].to_vec()).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    let deadline = Instant::now() + timeout;
+    let Some(status) = wait_until(&mut child, deadline) else {
+        let _ = child.kill();
+        let _ = child.wait();
+        return CommandOutcome::TimedOut(timeout);
+    };
+    let stderr_deadline = deadline.min(Instant::now() + STDERR_GRACE);
+    let mut stderr = Vec::new();
+    while let Ok(chunk) =
+        chunks.recv_timeout(stderr_deadline.saturating_duration_since(Instant::now()))
+    {
+        stderr.extend(chunk);
+    }
+    CommandOutcome::Exited {
+        status,
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+    }
+}
+
+fn wait_until(child: &mut Child, deadline: Instant) -> Option<ExitStatus> {
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+            _ => return None,
+        }
+    }
+}
+
+#[test]
+fn command_outcomes() {
+    let sh = |script: &str, background: bool, timeout: u64| {
+        run_command(
+            "sh",
+            &["-c".into(), script.into()],
+            background,
+            Duration::from_secs(timeout),
+        )
+    };
+    assert!(sh("exit 0", false, 5).success());
+    match sh("echo broken >&2; exit 3", false, 5) {
+        CommandOutcome::Exited { status, stderr } => {
+            assert_eq!(status.code(), Some(3));
+            assert_eq!(stderr, "broken\n");
+        }
+        outcome => panic!("unexpected outcome {}", outcome),
+    }
+
+    let start = Instant::now();
+    assert!(matches!(
+        sh("sleep 5", false, 0),
+        CommandOutcome::TimedOut(_)
+    ));
+    assert!(matches!(sh("sleep 5", true, 0), CommandOutcome::Detached));
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // the background sleep keeps stderr open after the shell exited
+    let start = Instant::now();
+    assert!(sh("sleep 30 & exit 0", false, 5).success());
+    match sh("echo broken >&2; sleep 30 & exit 0", false, 5) {
+        CommandOutcome::Exited { stderr, .. } => assert_eq!(stderr, "broken\n"),
+        outcome => panic!("unexpected outcome {}", outcome),
+    }
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert!(matches!(
+        run_command("/nonexistent/hyprdock", &[], false, DEFAULT_TIMEOUT),
+        CommandOutcome::SpawnFailed(_)
+    ));
+}
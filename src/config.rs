@@ -0,0 +1,428 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    collections::BTreeMap,
+    env, fmt, fs, io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use optional_struct::Applicable;
+
+use crate::{DEFAULT_CONFIG, HyprDock, OptionalHyprDock};
+
+/// Modes understood by `add_monitor`.
+const EXTERNAL_MODES: [&str; 2] = ["extend", "mirror"];
+const COMMAND_KEYS: [&str; 5] = ["base", "args", "shell", "background", "timeout"];
+const LOCK_READINESS_KEYS: [&str; 5] = ["probe", "ms", "path", "pattern", "max_wait"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The setting can not be used, hyprdock uses its default instead.
+    /// If the file is not valid TOML, the whole config falls back to the defaults.
+    Error,
+    /// The config is used, but part of it is probably not doing what was intended.
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+pub(crate) fn default_config() -> HyprDock {
+    DEFAULT_CONFIG.to_owned().try_into().unwrap()
+}
+
+/// Reads and checks the config. Invalid settings are reported and replaced by their defaults,
+/// `None` is returned if the file can not be read or is not valid TOML.
+/// A missing file is not an error, the default config is used.
+pub(crate) fn load_config(path: &str) -> (Option<HyprDock>, Vec<Diagnostic>) {
+    match fs::read_to_string(path) {
+        Ok(contents) => parse_config_str(&contents),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (Some(default_config()), Vec::new()),
+        Err(err) => (
+            None,
+            vec![Diagnostic::error(format!(
+                "Could not read {}: {}",
+                path, err
+            ))],
+        ),
+    }
+}
+
+/// Reads the config for a reload of the running daemon. Unlike `load_config`, a missing file
+/// or invalid settings keep the running config, the defaults would silently replace it.
+pub(crate) fn reload_config(path: &str) -> (Result<HyprDock, String>, Vec<Diagnostic>) {
+    if !Path::new(path).exists() {
+        return (
+            Err(format!(
+                "{} does not exist, keeping the current config",
+                path
+            )),
+            Vec::new(),
+        );
+    }
+    let (config, diagnostics) = load_config(path);
+    let config = match config {
+        Some(config) if !diagnostics.iter().any(Diagnostic::is_error) => Ok(config),
+        _ => Err(format!(
+            "{} has errors, keeping the current config. Run hyprdock config check for details",
+            path
+        )),
+    };
+    (config, diagnostics)
+}
+
+pub(crate) fn parse_config_str(contents: &str) -> (Option<HyprDock>, Vec<Diagnostic>) {
+    let mut table = match contents.parse::<toml::Table>() {
+        Ok(table) => table,
+        Err(err) => return (None, vec![toml_error(contents, &err)]),
+    };
+    let mut diagnostics = unknown_keys(&table);
+    // only used to report where an invalid setting is
+    let spans: BTreeMap<String, toml::Spanned<toml::Value>> =
+        toml::from_str(contents).unwrap_or_default();
+    let mut drop_invalid = |table: &mut toml::Table, key: &str, message: &str| {
+        let message = format!("{}: {}, using the default", key, message);
+        diagnostics.push(match spans.get(key) {
+            Some(value) => {
+                let (line, column) = line_column(contents, value.span().start);
+                Diagnostic::error(format!("line {}, column {}: {}", line, column, message))
+            }
+            None => Diagnostic::error(message),
+        });
+        table.remove(key);
+    };
+
+    let wrong_types: Vec<(String, String)> = table
+        .iter()
+        .filter_map(|(key, value)| {
+            let setting = toml::Table::from_iter([(key.clone(), value.clone())]);
+            let err = setting.try_into::<OptionalHyprDock>().err()?;
+            Some((key.clone(), err.message().trim().to_string()))
+        })
+        .collect();
+    for (key, message) in wrong_types {
+        drop_invalid(&mut table, &key, &message);
+    }
+    let Some(mut config) = build_config(&table) else {
+        return (None, diagnostics);
+    };
+    let invalid_values = config.invalid_values();
+    if !invalid_values.is_empty() {
+        for (key, message) in invalid_values {
+            drop_invalid(&mut table, key, &message);
+        }
+        let Some(valid) = build_config(&table) else {
+            return (None, diagnostics);
+        };
+        config = valid;
+    }
+    diagnostics.extend(config.warnings());
+    (Some(config), diagnostics)
+}
+
+/// The settings of the table on top of the defaults.
+fn build_config(table: &toml::Table) -> Option<HyprDock> {
+    let parsed: OptionalHyprDock = table.clone().try_into().ok()?;
+    Some(parsed.build(default_config()))
+}
+
+/// 1-based line and column of a byte offset.
+fn line_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+fn toml_error(contents: &str, err: &toml::de::Error) -> Diagnostic {
+    let message = err.message().trim();
+    match err.span() {
+        Some(span) => {
+            let (line, column) = line_column(contents, span.start);
+            Diagnostic::error(format!("line {}, column {}: {}", line, column, message))
+        }
+        None => Diagnostic::error(message),
+    }
+}
+
+/// Unknown keys are ignored when parsing, which hides typos.
+fn unknown_keys(table: &toml::Table) -> Vec<Diagnostic> {
+    let known = toml::Table::try_from(&*DEFAULT_CONFIG).unwrap_or_default();
+    let mut diagnostics = Vec::new();
+    for (key, value) in table {
+        if !known.contains_key(key) {
+            diagnostics.push(Diagnostic::warning(format!("Unknown key {}", key)));
+            continue;
+        }
+        let nested_keys: &[&str] = if key.ends_with("_command") {
+            &COMMAND_KEYS
+        } else if key == "lock_readiness" {
+            &LOCK_READINESS_KEYS
+        } else {
+            continue;
+        };
+        let Some(nested) = value.as_table() else {
+            continue;
+        };
+        for nested_key in nested.keys() {
+            if !nested_keys.contains(&nested_key.as_str()) {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "Unknown key {}.{}",
+                    key, nested_key
+                )));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Searches `$PATH` like a shell would, paths containing a `/` are checked directly.
+fn find_binary(name: &str) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        path.metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+    };
+    if name.contains('/') {
+        let path = PathBuf::from(name);
+        return is_executable(&path).then_some(path);
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| is_executable(path))
+}
+
+impl HyprDock {
+    /// Settings that parsed, but can not be used, along with the reason.
+    fn invalid_values(&self) -> Vec<(&'static str, String)> {
+        let mut invalid = Vec::new();
+        if !EXTERNAL_MODES.contains(&self.default_external_mode.as_str()) {
+            invalid.push((
+                "default_external_mode",
+                format!(
+                    "Invalid mode {}, expected one of {}",
+                    self.default_external_mode,
+                    EXTERNAL_MODES.join(", ")
+                ),
+            ));
+        }
+        for (key, command) in self.commands() {
+            if let Err(err) = command.format(|_| String::new()) {
+                invalid.push((key, err.to_string()));
+            }
+        }
+        invalid
+    }
+
+    /// Problems in a usable config.
+    fn warnings(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for (key, command) in self.commands() {
+            let Ok(formatted) = command.format(|_| String::new()) else {
+                continue;
+            };
+            let base = formatted.base.trim();
+            if !base.is_empty() && find_binary(base).is_none() {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "{}: {} was not found in $PATH",
+                    key, base
+                )));
+            }
+        }
+        diagnostics
+    }
+
+    /// The settings that differ in `new`, as `key: old -> new`.
+    pub fn changes(&self, new: &HyprDock) -> Vec<String> {
+        let (Ok(old), Ok(new)) = (toml::Table::try_from(self), toml::Table::try_from(new)) else {
+            return Vec::new();
+        };
+        old.iter()
+            .filter_map(|(key, value)| {
+                let new_value = new.get(key)?;
+                (value != new_value).then(|| format!("{}: {} -> {}", key, value, new_value))
+            })
+            .collect()
+    }
+}
+
+/// The default commands are not installed everywhere, so binaries are only checked explicitly.
+#[cfg(test)]
+fn check(contents: &str) -> (Option<HyprDock>, Vec<Diagnostic>) {
+    let (config, diagnostics) = parse_config_str(contents);
+    let diagnostics = diagnostics
+        .into_iter()
+        .filter(|diagnostic| !diagnostic.message.ends_with("was not found in $PATH"))
+        .collect();
+    (config, diagnostics)
+}
+
+#[test]
+fn config_diagnostics() {
+    let (_, diagnostics) = check("monitor_name = \"eDP-1\"\nconfirm_timeout = 15\n");
+    assert_eq!(diagnostics, Vec::new());
+
+    // only invalid TOML discards the whole config
+    let (config, diagnostics) = check("monitor_name = \"eDP-2\"\nconfirm_timeout = \n");
+    assert!(config.is_none());
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].is_error());
+    assert!(diagnostics[0].message.starts_with("line 2, column "));
+
+    let (config, diagnostics) =
+        check("monitor_name = \"eDP-2\"\n\nconfirm_timeout = \"x\"\n[lock_command]\nbase = 5\n");
+    let config = config.unwrap();
+    assert_eq!(config.monitor_name, "eDP-2");
+    assert_eq!(config.confirm_timeout, 15);
+    assert_eq!(config.lock_command.base, "hyprlock");
+    assert_eq!(
+        diagnostics,
+        vec![
+            Diagnostic::error(
+                "line 3, column 19: confirm_timeout: invalid type: string \"x\", expected u64, \
+                 using the default"
+            ),
+            Diagnostic::error(
+                "line 4, column 1: lock_command: invalid type: integer `5`, expected a string, \
+                 using the default"
+            ),
+        ]
+    );
+
+    let (config, diagnostics) =
+        check("monitor_nmae = \"eDP-2\"\n[lock_command]\nbase = \"sh\"\nbackgrond = true\n");
+    assert_eq!(config.unwrap().lock_command.base, "sh");
+    assert_eq!(
+        diagnostics,
+        vec![
+            Diagnostic::warning("Unknown key lock_command.backgrond"),
+            Diagnostic::warning("Unknown key monitor_nmae"),
+        ]
+    );
+
+    let (config, diagnostics) =
+        check("monitor_name = \"eDP-2\"\ndefault_external_mode = \"external\"\n");
+    let config = config.unwrap();
+    assert_eq!(config.monitor_name, "eDP-2");
+    assert_eq!(config.default_external_mode, "extend");
+    assert_eq!(
+        diagnostics,
+        vec![Diagnostic::error(
+            "line 2, column 25: default_external_mode: Invalid mode external, \
+             expected one of extend, mirror, using the default"
+        )]
+    );
+
+    let (config, diagnostics) = check(
+        "[utility_command]\nbase = \"hyprdock-missing-binary\"\nargs = [\"{monitor}\"]\n\
+         [wallpaper_command]\nbase = \"swww\"\n",
+    );
+    let config = config.unwrap();
+    assert_eq!(config.utility_command.base, "");
+    assert_eq!(config.wallpaper_command.base, "swww");
+    assert_eq!(
+        diagnostics,
+        vec![Diagnostic::error(
+            "line 1, column 1: utility_command: Unknown placeholder {monitor}, expected one of \
+             {internal}, {externals}, {first_external}, {profile}, {mode}, {lid}, \
+             or {{ for a literal {, using the default"
+        )]
+    );
+
+    let (config, diagnostics) =
+        parse_config_str("[utility_command]\nbase = \"hyprdock-missing-binary\"\nargs = []\n");
+    assert_eq!(
+        config.unwrap().utility_command.base,
+        "hyprdock-missing-binary"
+    );
+    assert!(diagnostics.contains(&Diagnostic::warning(
+        "utility_command: hyprdock-missing-binary was not found in $PATH"
+    )));
+    assert!(find_binary("sh").is_some());
+}
+
+#[test]
+fn config_reload() {
+    let path = env::temp_dir().join(format!("hyprdock-reload-{}.toml", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = fs::remove_file(path);
+    assert!(reload_config(path).0.is_err());
+
+    fs::write(path, "confirm_timeout = 30\n").unwrap();
+    assert_eq!(reload_config(path).0.unwrap().confirm_timeout, 30);
+
+    // a single invalid setting must not reset the running value to its default
+    fs::write(
+        path,
+        "confirm_timeout = 30\ndefault_external_mode = \"sideways\"\n",
+    )
+    .unwrap();
+    let (config, diagnostics) = reload_config(path);
+    assert!(config.is_err());
+    assert!(diagnostics.iter().any(Diagnostic::is_error));
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn config_changes() {
+    let old = check("confirm_timeout = 15\n").0.unwrap();
+    let new = check("confirm_timeout = 30\n[utility_command]\nshell = \"true\"\n")
+        .0
+        .unwrap();
+    assert_eq!(old.changes(&old), Vec::<String>::new());
+    assert_eq!(
+        old.changes(&new),
+        vec![
+            "confirm_timeout: 15 -> 30".to_string(),
+            "utility_command: { args = [], base = \"\" } -> { args = [], base = \"\", shell = \"true\" }"
+                .to_string(),
+        ]
+    );
+}
@@ -0,0 +1,239 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{events::Event, inhibit::InhibitState, watch::ConfigChange};
+
+const SOCKET_NAME: &str = "hyprdock.sock";
+
+/// Commands accepted on the control socket, one JSON object per line,
+/// e.g. `{"command":"apply","mode":"extend"}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlRequest {
+    /// One of `internal`, `external`, `extend` or `mirror`.
+    Apply {
+        mode: String,
+    },
+    /// Imports the named profile, or the one for the connected monitors.
+    Import {
+        #[serde(default)]
+        profile: Option<String>,
+    },
+    Status,
+    Reload,
+    /// Inhibits handling lid and display events until resumed.
+    Pause,
+    Resume,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ControlResponse {
+    pub ok: bool,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<DaemonStatus>,
+}
+
+impl ControlResponse {
+    pub fn ok(message: impl Into<String>) -> Self {
+        Self {
+            ok: true,
+            message: message.into(),
+            status: None,
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            message: message.into(),
+            status: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct DaemonStatus {
+    /// Set while automatic docking is inhibited.
+    pub inhibit: Option<InhibitState>,
+    pub active_profile: Option<String>,
+}
+
+/// Everything the daemon loop reacts to, hardware events and control requests
+/// arrive through the same channel so they are handled one after another.
+pub enum DaemonMessage {
+    /// An event along with the time it was received.
+    Event(Event, Instant),
+    Control(ControlRequest, Sender<ControlResponse>),
+    ConfigChanged(ConfigChange),
+}
+
+pub fn socket_path() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join(SOCKET_NAME))
+}
+
+/// Forwards event source events into the daemon channel.
+pub fn forward_events(events: Receiver<Event>, sender: Sender<DaemonMessage>) {
+    thread::spawn(move || {
+        for event in events {
+            if sender
+                .send(DaemonMessage::Event(event, Instant::now()))
+                .is_err()
+            {
+                return;
+            }
+        }
+    });
+}
+
+/// Binds the control socket, replacing a stale one left behind by a crashed daemon.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    if UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("another daemon is listening on {}", path.display()),
+        ));
+    }
+    let _ = fs::remove_file(path);
+    UnixListener::bind(path)
+}
+
+/// Accepts clients on their own thread and passes their requests to the daemon.
+pub fn serve(listener: UnixListener, sender: Sender<DaemonMessage>) {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            if let Err(err) = handle_client(stream, &sender) {
+                println!("Control socket client failed: {}", err);
+            }
+        }
+    });
+}
+
+fn handle_client(stream: UnixStream, sender: &Sender<DaemonMessage>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let response = match serde_json::from_str(&line) {
+        Ok(request) => {
+            let (reply, response) = mpsc::channel();
+            sender
+                .send(DaemonMessage::Control(request, reply))
+                .map_err(|_| io::Error::other("daemon stopped"))?;
+            response
+                .recv()
+                .unwrap_or_else(|_| ControlResponse::error("daemon did not answer"))
+        }
+        Err(err) => ControlResponse::error(format!("Invalid request: {}", err)),
+    };
+    write_message(&stream, &response)
+}
+
+fn write_message(mut stream: &UnixStream, message: &impl Serialize) -> io::Result<()> {
+    let mut contents = serde_json::to_string(message).map_err(io::Error::other)?;
+    contents.push('\n');
+    stream.write_all(contents.as_bytes())
+}
+
+/// Sends the request and waits for the answer, at most for `timeout` if one is given.
+pub fn send_request(
+    path: &Path,
+    request: &ControlRequest,
+    timeout: Option<Duration>,
+) -> io::Result<ControlResponse> {
+    let stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(timeout)?;
+    write_message(&stream, request)?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(io::Error::other)
+}
+
+/// Sends the request to a running daemon.
+/// Returns `None` if no daemon is running, so the caller can handle it itself.
+pub fn forward(request: &ControlRequest) -> Option<ControlResponse> {
+    let path = socket_path()?;
+    match send_request(&path, request, None) {
+        Ok(response) => Some(response),
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            None
+        }
+        Err(err) => Some(ControlResponse::error(format!(
+            "Could not talk to the daemon: {}",
+            err
+        ))),
+    }
+}
+
+#[test]
+fn control_socket_round_trip() {
+    let path = env::temp_dir().join(format!("hyprdock-control-{}.sock", std::process::id()));
+    let listener = bind(&path).expect("Could not bind control socket");
+    assert!(bind(&path).is_err());
+    let (sender, receiver) = mpsc::channel();
+    serve(listener, sender);
+    let daemon = thread::spawn(move || {
+        let mut requests = Vec::new();
+        for message in receiver.iter().take(2) {
+            if let DaemonMessage::Control(request, reply) = message {
+                reply.send(ControlResponse::ok("done")).unwrap();
+                requests.push(request);
+            }
+        }
+        requests
+    });
+
+    let apply = ControlRequest::Apply {
+        mode: "extend".into(),
+    };
+    assert_eq!(
+        send_request(&path, &apply, None).unwrap(),
+        ControlResponse::ok("done")
+    );
+    assert_eq!(
+        send_request(&path, &ControlRequest::Pause, None).unwrap(),
+        ControlResponse::ok("done")
+    );
+    assert_eq!(daemon.join().unwrap(), vec![apply, ControlRequest::Pause]);
+
+    let stream = UnixStream::connect(&path).unwrap();
+    (&stream).write_all(b"{\"command\":\"explode\"}\n").unwrap();
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line).unwrap();
+    let response: ControlResponse = serde_json::from_str(&line).unwrap();
+    assert!(!response.ok);
+    let _ = fs::remove_file(path);
+}
@@ -0,0 +1,267 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    io::{BufRead, BufReader},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    sync::mpsc::Sender,
+    thread,
+    time::Duration,
+};
+
+use super::{Event, EventSource, SourceError};
+
+const ACPID_SOCKET: &str = "/var/run/acpid.socket";
+const RECONNECT_BACKOFF_START: Duration = Duration::from_secs(1);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+pub struct AcpidSource {
+    path: PathBuf,
+    stream: Option<BufReader<UnixStream>>,
+}
+
+impl Default for AcpidSource {
+    fn default() -> Self {
+        Self::with_path(PathBuf::from(ACPID_SOCKET))
+    }
+}
+
+/// A single acpid event line, e.g. `ac_adapter ACPI0003:00 00000080 00000001`.
+/// Input layer events like `button/lid LID close` only have three fields,
+/// the missing value is left empty.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AcpiEvent {
+    pub class: String,
+    pub device: String,
+    pub code: String,
+    pub value: String,
+}
+
+impl AcpiEvent {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        Some(Self {
+            class: fields.next()?.to_string(),
+            device: fields.next().unwrap_or_default().to_string(),
+            code: fields.next().unwrap_or_default().to_string(),
+            value: fields.next().unwrap_or_default().to_string(),
+        })
+    }
+
+    pub fn to_event(&self) -> Option<Event> {
+        match (self.class.as_str(), self.code.as_str()) {
+            ("button/lid", "close") => Some(Event::LidClosed),
+            ("button/lid", "open") => Some(Event::LidOpened),
+            ("jack/videoout", "plug") => Some(Event::DisplayConnected {
+                name: None,
+                edid: None,
+            }),
+            ("jack/videoout", "unplug") => Some(Event::DisplayDisconnected { name: None }),
+            ("ac_adapter", _) => Some(Event::PowerSourceChanged {
+                on_ac: u32::from_str_radix(&self.value, 16).ok()? != 0,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl AcpidSource {
+    pub fn with_path(path: PathBuf) -> Self {
+        Self { path, stream: None }
+    }
+
+    /// Reads events until the socket closes or fails and returns whether any event was read.
+    /// Only returns an error if the events can no longer be delivered.
+    fn read_events(
+        &self,
+        stream: &mut BufReader<UnixStream>,
+        sender: &Sender<Event>,
+    ) -> Result<bool, SourceError> {
+        let mut line = Vec::new();
+        let mut read_any = false;
+        loop {
+            line.clear();
+            match stream.read_until(b'\n', &mut line) {
+                Ok(0) => {
+                    println!("acpid socket closed, reconnecting");
+                    return Ok(read_any);
+                }
+                Err(err) => {
+                    println!("Could not read from acpid socket: {}, reconnecting", err);
+                    return Ok(read_any);
+                }
+                // a partial line at EOF is dropped, acpid always terminates its events
+                Ok(_) if line.last() != Some(&b'\n') => return Ok(read_any),
+                Ok(_) => read_any = true,
+            }
+            let event = AcpiEvent::parse(&String::from_utf8_lossy(&line))
+                .and_then(|event| event.to_event());
+            if let Some(event) = event {
+                sender.send(event)?;
+            }
+        }
+    }
+}
+
+impl EventSource for AcpidSource {
+    fn name(&self) -> &'static str {
+        "acpid"
+    }
+
+    fn connect(&mut self) -> Result<(), SourceError> {
+        let stream = UnixStream::connect(&self.path)
+            .map_err(|err| format!("{}, do you have the service installed and running?", err))?;
+        self.stream = Some(BufReader::new(stream));
+        Ok(())
+    }
+
+    fn run(&mut self, sender: &Sender<Event>) -> Result<(), SourceError> {
+        let mut backoff = RECONNECT_BACKOFF_START;
+        loop {
+            let mut stream = match self.stream.take() {
+                Some(stream) => stream,
+                None => match UnixStream::connect(&self.path) {
+                    Ok(stream) => BufReader::new(stream),
+                    Err(_) => {
+                        thread::sleep(backoff);
+                        backoff = (backoff * 2).min(RECONNECT_BACKOFF_MAX);
+                        continue;
+                    }
+                },
+            };
+            // a socket that accepts and closes right away must not cause a tight reconnect loop,
+            // so the backoff is only reset once the connection delivered an event
+            if self.read_events(&mut stream, sender)? {
+                backoff = RECONNECT_BACKOFF_START;
+            } else {
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(RECONNECT_BACKOFF_MAX);
+            }
+        }
+    }
+}
+
+#[test]
+fn acpid_parse() {
+    let event = AcpiEvent::parse("ac_adapter ACPI0003:00 00000080 00000000\n").unwrap();
+    assert_eq!(
+        event,
+        AcpiEvent {
+            class: "ac_adapter".into(),
+            device: "ACPI0003:00".into(),
+            code: "00000080".into(),
+            value: "00000000".into(),
+        }
+    );
+    assert_eq!(
+        event.to_event(),
+        Some(Event::PowerSourceChanged { on_ac: false })
+    );
+    assert_eq!(
+        AcpiEvent::parse("button/lid LID close").and_then(|event| event.to_event()),
+        Some(Event::LidClosed)
+    );
+    assert_eq!(
+        AcpiEvent::parse("button/power PBTN 00000080 00000000").and_then(|event| event.to_event()),
+        None
+    );
+}
+
+#[test]
+fn acpid_framing_and_reconnect() {
+    use std::{io::Write, os::unix::net::UnixListener, sync::mpsc};
+    let path = std::env::temp_dir().join(format!("hyprdock-acpid-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+
+    let mut source = AcpidSource::with_path(path.clone());
+    source.connect().expect("Could not connect to fake acpid");
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || source.run(&sender));
+
+    let (mut stream, _) = listener.accept().unwrap();
+    // one event split over two writes, followed by two coalesced events
+    stream
+        .write_all(b"button/lid LID close\nbutton/lid LI")
+        .unwrap();
+    stream.flush().unwrap();
+    thread::sleep(Duration::from_millis(50));
+    stream
+        .write_all(b"D open\njack/videoout VIDEOOUT plug\njack/videoout VIDEOOUT unplug\n")
+        .unwrap();
+    drop(stream);
+
+    // acpid restarted
+    let (mut stream, _) = listener.accept().unwrap();
+    stream
+        .write_all(b"ac_adapter ACPI0003:00 00000080 00000001\n")
+        .unwrap();
+
+    let events: Vec<Event> = (0..5)
+        .map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap())
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            Event::LidClosed,
+            Event::LidOpened,
+            Event::DisplayConnected {
+                name: None,
+                edid: None
+            },
+            Event::DisplayDisconnected { name: None },
+            Event::PowerSourceChanged { on_ac: true },
+        ]
+    );
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn acpid_reconnect_backoff() {
+    use std::{os::unix::net::UnixListener, sync::mpsc, time::Instant};
+    let path = std::env::temp_dir().join(format!(
+        "hyprdock-acpid-backoff-{}.sock",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    listener.set_nonblocking(true).unwrap();
+
+    let mut source = AcpidSource::with_path(path.clone());
+    let (sender, _receiver) = mpsc::channel();
+    thread::spawn(move || source.run(&sender));
+
+    // every connection is closed right away without sending an event
+    let mut connections = 0;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(1500) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                connections += 1;
+                drop(stream);
+            }
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+    assert!(
+        (1..=2).contains(&connections),
+        "Expected a backoff between reconnects, got {} connections",
+        connections
+    );
+    let _ = std::fs::remove_file(path);
+}
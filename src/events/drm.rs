@@ -0,0 +1,271 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    collections::BTreeMap,
+    fs, io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::PathBuf,
+    sync::mpsc::Sender,
+};
+
+use crate::monitors::edid::Edid;
+
+use super::{Event, EventSource, SourceError};
+
+const DRM_SYSFS: &str = "/sys/class/drm";
+/// Multicast group the kernel sends its uevents to.
+const KERNEL_UEVENT_GROUP: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Connector {
+    connected: bool,
+    edid: Option<Edid>,
+}
+
+/// Connector hotplug straight from the kernel: listens to `drm` uevents on a
+/// netlink socket and reads connector status and EDID from sysfs.
+pub struct DrmSource {
+    sysfs: PathBuf,
+    socket: Option<OwnedFd>,
+    connectors: BTreeMap<String, Connector>,
+}
+
+impl Default for DrmSource {
+    fn default() -> Self {
+        Self::with_sysfs(PathBuf::from(DRM_SYSFS))
+    }
+}
+
+/// Strips the card prefix of a sysfs connector, `card1-DP-1` becomes `DP-1`.
+fn connector_name(entry: &str) -> Option<&str> {
+    let (card, name) = entry.split_once('-')?;
+    let index = card.strip_prefix("card")?;
+    if index.is_empty() || !index.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(name)
+}
+
+/// Reads the EDID of a connected connector, e.g. `DP-1`, from the sysfs drm class.
+pub fn connector_edid(connector: &str) -> Option<Edid> {
+    fs::read_dir(DRM_SYSFS).ok()?.flatten().find_map(|entry| {
+        let file_name = entry.file_name();
+        if file_name.to_str().and_then(connector_name) != Some(connector) {
+            return None;
+        }
+        Edid::parse(&fs::read(entry.path().join("edid")).ok()?)
+    })
+}
+
+/// Checks whether a raw kernel uevent (`ACTION@DEVPATH\0KEY=VALUE\0...`) is a drm hotplug.
+pub fn is_drm_hotplug(message: &[u8]) -> bool {
+    let mut fields = message.split(|byte| *byte == 0);
+    fields.next();
+    let mut drm = false;
+    let mut hotplug = false;
+    for field in fields {
+        match field {
+            b"SUBSYSTEM=drm" => drm = true,
+            b"HOTPLUG=1" => hotplug = true,
+            _ => (),
+        }
+    }
+    drm && hotplug
+}
+
+impl DrmSource {
+    /// Uses a different sysfs drm class directory, e.g. a fake tree in tests.
+    pub fn with_sysfs(sysfs: PathBuf) -> Self {
+        Self {
+            sysfs,
+            socket: None,
+            connectors: BTreeMap::new(),
+        }
+    }
+
+    fn scan(&self) -> io::Result<BTreeMap<String, Connector>> {
+        let mut connectors = BTreeMap::new();
+        for entry in fs::read_dir(&self.sysfs)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(name) = file_name.to_str().and_then(connector_name) else {
+                continue;
+            };
+            let Ok(status) = fs::read_to_string(entry.path().join("status")) else {
+                continue;
+            };
+            let connected = status.trim() == "connected";
+            let edid = if connected {
+                fs::read(entry.path().join("edid"))
+                    .ok()
+                    .and_then(|data| Edid::parse(&data))
+            } else {
+                None
+            };
+            connectors.insert(name.to_string(), Connector { connected, edid });
+        }
+        Ok(connectors)
+    }
+
+    /// Rescans sysfs and returns an event for every connector that changed since the last scan.
+    fn rescan(&mut self) -> io::Result<Vec<Event>> {
+        let connectors = self.scan()?;
+        let mut events = Vec::new();
+        for (name, connector) in connectors.iter() {
+            let was_connected = self
+                .connectors
+                .get(name)
+                .is_some_and(|previous| previous.connected);
+            if connector.connected && !was_connected {
+                events.push(Event::DisplayConnected {
+                    name: Some(name.clone()),
+                    edid: connector.edid.clone(),
+                });
+            } else if !connector.connected && was_connected {
+                events.push(Event::DisplayDisconnected {
+                    name: Some(name.clone()),
+                });
+            }
+        }
+        for (name, previous) in self.connectors.iter() {
+            if previous.connected && !connectors.contains_key(name) {
+                events.push(Event::DisplayDisconnected {
+                    name: Some(name.clone()),
+                });
+            }
+        }
+        self.connectors = connectors;
+        Ok(events)
+    }
+}
+
+fn uevent_socket() -> io::Result<OwnedFd> {
+    // SAFETY: plain socket syscalls, the fd is owned right after creation
+    unsafe {
+        let fd = libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            libc::NETLINK_KOBJECT_UEVENT,
+        );
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = OwnedFd::from_raw_fd(fd);
+        let mut address: libc::sockaddr_nl = mem::zeroed();
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = KERNEL_UEVENT_GROUP;
+        let res = libc::bind(
+            socket.as_raw_fd(),
+            &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+            mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        );
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(socket)
+    }
+}
+
+impl EventSource for DrmSource {
+    fn name(&self) -> &'static str {
+        "drm"
+    }
+
+    fn connect(&mut self) -> Result<(), SourceError> {
+        self.socket = Some(uevent_socket()?);
+        self.connectors = self.scan()?;
+        Ok(())
+    }
+
+    fn run(&mut self, sender: &Sender<Event>) -> Result<(), SourceError> {
+        let socket = self.socket.take().ok_or("uevent socket is not connected")?;
+        let mut buf = [0u8; 8192];
+        loop {
+            // SAFETY: recv writes at most buf.len() bytes into buf
+            let n = unsafe {
+                libc::recv(
+                    socket.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                )
+            };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err.into());
+            }
+            if !is_drm_hotplug(&buf[..n as usize]) {
+                continue;
+            }
+            for event in self.rescan()? {
+                sender.send(event)?;
+            }
+        }
+    }
+}
+
+#[test]
+fn drm_fake_sysfs() {
+    use crate::monitors::edid::example_edid;
+    let root = std::env::temp_dir().join(format!("hyprdock-drm-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let connector = |name: &str, status: &str, edid: &[u8]| {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("status"), format!("{}\n", status)).unwrap();
+        fs::write(dir.join("edid"), edid).unwrap();
+    };
+    connector("card1-eDP-1", "connected", &example_edid("Panel", ""));
+    connector("card1-DP-1", "disconnected", &[]);
+    fs::create_dir_all(root.join("renderD128")).unwrap();
+
+    let mut source = DrmSource::with_sysfs(root.clone());
+    assert_eq!(source.rescan().unwrap().len(), 1);
+
+    connector(
+        "card1-DP-1",
+        "connected",
+        &example_edid("DELL U2719D", "7KXJ8T2"),
+    );
+    let events = source.rescan().unwrap();
+    let [Event::DisplayConnected { name, edid }] = events.as_slice() else {
+        panic!("Expected a single connect event, got {:?}", events);
+    };
+    assert_eq!(name.as_deref(), Some("DP-1"));
+    assert_eq!(edid.as_ref().unwrap().serial, "7KXJ8T2");
+
+    fs::remove_dir_all(root.join("card1-DP-1")).unwrap();
+    assert_eq!(
+        source.rescan().unwrap(),
+        vec![Event::DisplayDisconnected {
+            name: Some("DP-1".into())
+        }]
+    );
+    assert!(source.rescan().unwrap().is_empty());
+    let _ = fs::remove_dir_all(root);
+
+    assert!(is_drm_hotplug(
+        b"change@/devices/pci0000:00/0000:00:02.0/drm/card1\0ACTION=change\0SUBSYSTEM=drm\0HOTPLUG=1\0"
+    ));
+    assert!(!is_drm_hotplug(
+        b"add@/devices/usb1\0ACTION=add\0SUBSYSTEM=usb\0"
+    ));
+}
@@ -0,0 +1,260 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    mem,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    thread,
+};
+
+use super::{Event, EventSource, SourceError};
+
+const INPUT_DIR: &str = "/dev/input";
+const EV_SW: u16 = 0x05;
+const SW_LID: u16 = 0x00;
+const SW_TABLET_MODE: u16 = 0x01;
+const SW_CNT: usize = 0x11;
+const TIMEVAL_SIZE: usize = mem::size_of::<libc::timeval>();
+/// Size of `struct input_event`: a timeval followed by type, code and value.
+const INPUT_EVENT_SIZE: usize = TIMEVAL_SIZE + 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl InputEvent {
+    pub fn from_bytes(bytes: &[u8; INPUT_EVENT_SIZE]) -> Self {
+        let field = &bytes[TIMEVAL_SIZE..];
+        Self {
+            kind: u16::from_ne_bytes([field[0], field[1]]),
+            code: u16::from_ne_bytes([field[2], field[3]]),
+            value: i32::from_ne_bytes([field[4], field[5], field[6], field[7]]),
+        }
+    }
+
+    pub fn to_event(self) -> Option<Event> {
+        if self.kind != EV_SW {
+            return None;
+        }
+        switch_event(self.code, self.value != 0)
+    }
+}
+
+fn switch_event(code: u16, active: bool) -> Option<Event> {
+    match (code, active) {
+        (SW_LID, true) => Some(Event::LidClosed),
+        (SW_LID, false) => Some(Event::LidOpened),
+        (SW_TABLET_MODE, enabled) => Some(Event::TabletModeChanged { enabled }),
+        _ => None,
+    }
+}
+
+/// Reads the next event, returns `None` at the end of a replay file.
+pub fn read_input_event(reader: &mut impl Read) -> io::Result<Option<InputEvent>> {
+    let mut buf = [0; INPUT_EVENT_SIZE];
+    match reader.read_exact(&mut buf) {
+        Ok(()) => Ok(Some(InputEvent::from_bytes(&buf))),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// `_IOC(_IOC_READ, 'E', nr, size)` from linux/input.h
+fn eviocg(nr: u8, size: usize) -> u64 {
+    (2 << 30) | ((size as u64) << 16) | ((b'E' as u64) << 8) | nr as u64
+}
+
+/// Queries a switch bitmap via `EVIOCGBIT(EV_SW)` or `EVIOCGSW`.
+/// Fails for anything that is not an input device, e.g. replay files.
+fn switch_bits(file: &File, nr: u8) -> io::Result<[u8; SW_CNT.div_ceil(8)]> {
+    let mut bits = [0u8; SW_CNT.div_ceil(8)];
+    let request = eviocg(nr, bits.len());
+    // SAFETY: the kernel writes at most bits.len() bytes, as encoded in the request
+    let res = unsafe { libc::ioctl(file.as_raw_fd(), request as _, bits.as_mut_ptr()) };
+    if res < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(bits)
+}
+
+fn has_bit(bits: &[u8], bit: u16) -> bool {
+    bits[bit as usize / 8] & (1 << (bit % 8)) != 0
+}
+
+fn supported_switches(file: &File) -> Vec<u16> {
+    let Ok(bits) = switch_bits(file, 0x20 + EV_SW as u8) else {
+        return Vec::new();
+    };
+    [SW_LID, SW_TABLET_MODE]
+        .into_iter()
+        .filter(|switch| has_bit(&bits, *switch))
+        .collect()
+}
+
+/// Lid and tablet mode switches read directly from the kernel input devices.
+/// Unlike acpid, this also knows the state of the switches at startup.
+#[derive(Default)]
+pub struct EvdevSource {
+    devices: Vec<PathBuf>,
+    files: Vec<File>,
+    initial: Vec<Event>,
+}
+
+impl EvdevSource {
+    /// Uses the given device or replay file instead of searching `/dev/input`.
+    pub fn with_device(path: PathBuf) -> Self {
+        Self {
+            devices: vec![path],
+            ..Default::default()
+        }
+    }
+
+    fn discover() -> io::Result<Vec<PathBuf>> {
+        let mut devices = Vec::new();
+        for entry in fs::read_dir(INPUT_DIR)? {
+            let path = entry?.path();
+            let is_event_device = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("event"));
+            if !is_event_device {
+                continue;
+            }
+            if let Ok(file) = File::open(&path)
+                && !supported_switches(&file).is_empty()
+            {
+                devices.push(path);
+            }
+        }
+        devices.sort();
+        Ok(devices)
+    }
+
+    fn open(&mut self, path: &Path) -> io::Result<()> {
+        let file = File::open(path)?;
+        if let Ok(state) = switch_bits(&file, 0x1b) {
+            for switch in supported_switches(&file) {
+                let active = has_bit(&state, switch);
+                // handling a lid that is already closed would suspend right after startup
+                let event = match switch {
+                    SW_LID => Some(Event::LidState { closed: active }),
+                    _ => switch_event(switch, active),
+                };
+                self.initial.extend(event);
+            }
+        }
+        self.files.push(file);
+        Ok(())
+    }
+}
+
+fn read_device(mut file: File, sender: &Sender<Event>) -> Result<(), SourceError> {
+    while let Some(input) = read_input_event(&mut file)? {
+        if let Some(event) = input.to_event() {
+            sender.send(event)?;
+        }
+    }
+    Err("input device closed".into())
+}
+
+impl EventSource for EvdevSource {
+    fn name(&self) -> &'static str {
+        "evdev"
+    }
+
+    fn connect(&mut self) -> Result<(), SourceError> {
+        if self.devices.is_empty() {
+            self.devices = Self::discover()?;
+        }
+        if self.devices.is_empty() {
+            return Err(format!(
+                "No readable lid or tablet mode switch in {}, is your user in the input group?",
+                INPUT_DIR
+            )
+            .into());
+        }
+        for device in self.devices.clone() {
+            self.open(&device)
+                .map_err(|err| format!("{}: {}", device.display(), err))?;
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, sender: &Sender<Event>) -> Result<(), SourceError> {
+        for event in self.initial.drain(..) {
+            sender.send(event)?;
+        }
+        let mut files = mem::take(&mut self.files);
+        let last = files.pop().ok_or("evdev source is not connected")?;
+        for file in files {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                if let Err(err) = read_device(file, &sender) {
+                    println!("Event source evdev stopped: {}", err);
+                }
+            });
+        }
+        read_device(last, sender)
+    }
+}
+
+#[test]
+fn evdev_replay() {
+    use std::{io::Write, sync::mpsc};
+    let record = |kind: u16, code: u16, value: i32| {
+        let mut bytes = vec![0; TIMEVAL_SIZE];
+        bytes.extend(kind.to_ne_bytes());
+        bytes.extend(code.to_ne_bytes());
+        bytes.extend(value.to_ne_bytes());
+        bytes
+    };
+    let path = std::env::temp_dir().join(format!("hyprdock-evdev-{}.replay", std::process::id()));
+    let mut replay = File::create(&path).unwrap();
+    // EV_SYN reports are interleaved with the switch events on real devices
+    for bytes in [
+        record(EV_SW, SW_LID, 1),
+        record(0, 0, 0),
+        record(EV_SW, SW_TABLET_MODE, 1),
+        record(EV_SW, SW_LID, 0),
+        record(0, 0, 0),
+    ] {
+        replay.write_all(&bytes).unwrap();
+    }
+    drop(replay);
+
+    let mut source = EvdevSource::with_device(path.clone());
+    source.connect().expect("Could not open replay file");
+    let (sender, receiver) = mpsc::channel();
+    assert!(source.run(&sender).is_err());
+    drop(sender);
+    assert_eq!(
+        receiver.iter().collect::<Vec<_>>(),
+        vec![
+            Event::LidClosed,
+            Event::TabletModeChanged { enabled: true },
+            Event::LidOpened,
+        ]
+    );
+    let _ = fs::remove_file(path);
+}
@@ -0,0 +1,62 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::sync::mpsc::Sender;
+
+use crate::monitors::hypr_ipc::{HyprEvent, HyprEventListener};
+
+use super::{Event, EventSource, SourceError};
+
+/// Monitor hotplug as seen by hyprland on its event socket (`.socket2.sock`).
+#[derive(Default)]
+pub struct HyprlandSource {
+    listener: Option<HyprEventListener>,
+}
+
+impl From<HyprEvent> for Event {
+    fn from(event: HyprEvent) -> Self {
+        match event {
+            HyprEvent::MonitorAdded { name, .. } => Event::DisplayConnected {
+                name: Some(name),
+                edid: None,
+            },
+            HyprEvent::MonitorRemoved { name } => Event::DisplayDisconnected { name: Some(name) },
+        }
+    }
+}
+
+impl EventSource for HyprlandSource {
+    fn name(&self) -> &'static str {
+        "hyprland"
+    }
+
+    fn connect(&mut self) -> Result<(), SourceError> {
+        self.listener = Some(HyprEventListener::new()?);
+        Ok(())
+    }
+
+    fn run(&mut self, sender: &Sender<Event>) -> Result<(), SourceError> {
+        let listener = self
+            .listener
+            .as_mut()
+            .ok_or("hyprland event socket is not connected")?;
+        while let Some(event) = listener.next_event()? {
+            sender.send(event.into())?;
+        }
+        Err("hyprland event socket closed".into())
+    }
+}
@@ -0,0 +1,242 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+use zbus::{
+    blocking::{Connection, Proxy, connection, fdo::PropertiesProxy},
+    proxy::CacheProperties,
+    zvariant::OwnedFd,
+};
+
+use super::{Event, EventSource, SourceError};
+
+const LOGIND_DESTINATION: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const LOGIND_MANAGER: &str = "org.freedesktop.login1.Manager";
+/// Not every logind version announces changes of `LidClosed`,
+/// so the property is additionally polled at this interval.
+const LID_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Lid events from systemd-logind.
+/// While running, this source holds a `handle-lid-switch` inhibitor lock,
+/// which stops logind from suspending on lid close. The lock is released
+/// with the source, so logind takes over again if hyprdock dies.
+#[derive(Default)]
+pub struct LogindSource {
+    /// Bus address to use instead of the system bus.
+    address: Option<String>,
+    connection: Option<Connection>,
+    inhibitor: Option<OwnedFd>,
+    lid_closed: bool,
+}
+
+impl LogindSource {
+    pub fn with_address(address: String) -> Self {
+        Self {
+            address: Some(address),
+            ..Default::default()
+        }
+    }
+
+    fn manager(connection: &Connection) -> zbus::Result<Proxy<'static>> {
+        zbus::blocking::proxy::Builder::new(connection)
+            .destination(LOGIND_DESTINATION)?
+            .path(LOGIND_PATH)?
+            .interface(LOGIND_MANAGER)?
+            .cache_properties(CacheProperties::No)
+            .build()
+    }
+}
+
+/// Asks logind whether the lid is closed, `None` if logind is not reachable.
+pub fn query_lid_closed() -> Option<bool> {
+    let connection = Connection::system().ok()?;
+    LogindSource::manager(&connection)
+        .ok()?
+        .get_property("LidClosed")
+        .ok()
+}
+
+impl EventSource for LogindSource {
+    fn name(&self) -> &'static str {
+        "logind"
+    }
+
+    fn connect(&mut self) -> Result<(), SourceError> {
+        let connection = match &self.address {
+            Some(address) => connection::Builder::address(address.as_str())?.build()?,
+            None => Connection::system()?,
+        };
+        let manager = Self::manager(&connection)?;
+        self.lid_closed = manager.get_property("LidClosed")?;
+        let inhibitor: OwnedFd = manager.call(
+            "Inhibit",
+            &(
+                "handle-lid-switch",
+                "hyprdock",
+                "hyprdock handles lid events",
+                "block",
+            ),
+        )?;
+        self.inhibitor = Some(inhibitor);
+        self.connection = Some(connection);
+        Ok(())
+    }
+
+    fn run(&mut self, sender: &Sender<Event>) -> Result<(), SourceError> {
+        let connection = self.connection.clone().ok_or("logind is not connected")?;
+        let (lid_sender, lid_receiver) = mpsc::channel();
+
+        let signal_sender = lid_sender.clone();
+        let properties = PropertiesProxy::builder(&connection)
+            .destination(LOGIND_DESTINATION)?
+            .path(LOGIND_PATH)?
+            .build()?;
+        let changes = properties.receive_properties_changed()?;
+        thread::spawn(move || {
+            for signal in changes {
+                let Ok(args) = signal.args() else {
+                    continue;
+                };
+                if args.interface_name().as_str() != LOGIND_MANAGER {
+                    continue;
+                }
+                if let Some(lid_closed) = args
+                    .changed_properties()
+                    .get("LidClosed")
+                    .and_then(|value| bool::try_from(value).ok())
+                    && signal_sender.send(Ok(lid_closed)).is_err()
+                {
+                    return;
+                }
+            }
+        });
+
+        let manager = Self::manager(&connection)?;
+        thread::spawn(move || {
+            loop {
+                thread::sleep(LID_POLL_INTERVAL);
+                let lid_closed = manager.get_property::<bool>("LidClosed");
+                let failed = lid_closed.is_err();
+                if lid_sender.send(lid_closed).is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        for lid_closed in lid_receiver {
+            let lid_closed = lid_closed?;
+            if lid_closed == self.lid_closed {
+                continue;
+            }
+            self.lid_closed = lid_closed;
+            sender.send(if lid_closed {
+                Event::LidClosed
+            } else {
+                Event::LidOpened
+            })?;
+        }
+        Err("logind connection closed".into())
+    }
+}
+
+#[cfg(test)]
+struct FakeLogind {
+    lid_closed: bool,
+    inhibited: Vec<String>,
+}
+
+#[cfg(test)]
+#[zbus::interface(name = "org.freedesktop.login1.Manager")]
+impl FakeLogind {
+    fn inhibit(&mut self, what: String, _who: String, _why: String, _mode: String) -> OwnedFd {
+        self.inhibited.push(what);
+        std::os::fd::OwnedFd::from(std::fs::File::open("/dev/null").unwrap()).into()
+    }
+
+    #[zbus(property)]
+    fn lid_closed(&self) -> bool {
+        self.lid_closed
+    }
+}
+
+#[test]
+fn logind_lid_events() {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+    };
+    let daemon = Command::new("dbus-daemon")
+        .args(["--session", "--nofork", "--print-address"])
+        .stdout(Stdio::piped())
+        .spawn();
+    let Ok(mut daemon) = daemon else {
+        println!("dbus-daemon not found, skipping test");
+        return;
+    };
+    let mut address = String::new();
+    BufReader::new(daemon.stdout.take().unwrap())
+        .read_line(&mut address)
+        .unwrap();
+    let address = address.trim().to_string();
+
+    let logind = connection::Builder::address(address.as_str())
+        .unwrap()
+        .name(LOGIND_DESTINATION)
+        .unwrap()
+        .serve_at(
+            LOGIND_PATH,
+            FakeLogind {
+                lid_closed: false,
+                inhibited: Vec::new(),
+            },
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let mut source = LogindSource::with_address(address);
+    source.connect().expect("Could not connect to fake logind");
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || source.run(&sender));
+
+    let iface = logind
+        .object_server()
+        .interface::<_, FakeLogind>(LOGIND_PATH)
+        .unwrap();
+    assert_eq!(iface.get().inhibited, vec!["handle-lid-switch"]);
+    for closed in [true, false] {
+        iface.get_mut().lid_closed = closed;
+        zbus::block_on(iface.get().lid_closed_changed(iface.signal_emitter())).unwrap();
+        let expected = if closed {
+            Event::LidClosed
+        } else {
+            Event::LidOpened
+        };
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+            expected
+        );
+    }
+    daemon.kill().unwrap();
+    daemon.wait().unwrap();
+}
@@ -0,0 +1,279 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::monitors::edid::Edid;
+use std::{
+    error::Error,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub mod acpid;
+pub mod drm;
+pub mod evdev;
+pub mod hyprland;
+pub mod logind;
+
+pub type SourceError = Box<dyn Error + Send + Sync>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    LidClosed,
+    LidOpened,
+    /// The lid state at startup, it is only recorded by the filter and never handled.
+    LidState {
+        closed: bool,
+    },
+    /// Carries the connector name and EDID identity if the source knows them.
+    DisplayConnected {
+        name: Option<String>,
+        edid: Option<Edid>,
+    },
+    DisplayDisconnected {
+        name: Option<String>,
+    },
+    PowerSourceChanged {
+        on_ac: bool,
+    },
+    TabletModeChanged {
+        enabled: bool,
+    },
+}
+
+/// A producer of daemon events, e.g. acpid or the hyprland event socket.
+/// Every source runs on its own thread and reports into a shared channel.
+pub trait EventSource: Send {
+    fn name(&self) -> &'static str;
+
+    /// Called before the source is moved to its thread.
+    /// An error here means the source is not available on this system.
+    fn connect(&mut self) -> Result<(), SourceError>;
+
+    /// Blocks and sends events until the source is closed or the receiver is gone.
+    fn run(&mut self, sender: &Sender<Event>) -> Result<(), SourceError>;
+}
+
+/// Creates the source configured as `name` in `event_sources`.
+pub fn source_from_name(name: &str) -> Option<Box<dyn EventSource>> {
+    match name {
+        "acpid" => Some(Box::new(acpid::AcpidSource::default())),
+        "drm" => Some(Box::new(drm::DrmSource::default())),
+        "evdev" => Some(Box::new(evdev::EvdevSource::default())),
+        "hyprland" => Some(Box::new(hyprland::HyprlandSource::default())),
+        "logind" => Some(Box::new(logind::LogindSource::default())),
+        _ => None,
+    }
+}
+
+pub fn sources_from_config(names: &[String]) -> Vec<Box<dyn EventSource>> {
+    names
+        .iter()
+        .filter_map(|name| {
+            let source = source_from_name(name.trim());
+            if source.is_none() {
+                println!("Unknown event source {}, ignoring it", name);
+            }
+            source
+        })
+        .collect()
+}
+
+/// Connects all sources and starts them on their own threads.
+/// Returns `None` if not a single source could be connected.
+pub fn start_sources(sources: Vec<Box<dyn EventSource>>) -> Option<Receiver<Event>> {
+    let (sender, receiver) = mpsc::channel();
+    let mut started = 0;
+    for mut source in sources {
+        if let Err(err) = source.connect() {
+            println!("Could not start event source {}: {}", source.name(), err);
+            continue;
+        }
+        let sender = sender.clone();
+        thread::spawn(move || {
+            if let Err(err) = source.run(&sender) {
+                println!("Event source {} stopped: {}", source.name(), err);
+            }
+        });
+        started += 1;
+    }
+    if started == 0 { None } else { Some(receiver) }
+}
+
+/// Time after an accepted event in which further display events are considered
+/// duplicates, either because several sources reported the same hotplug or
+/// because hyprland reports the changes hyprdock itself just made.
+const EVENT_DEDUP_WINDOW: Duration = Duration::from_secs(2);
+
+pub struct EventFilter {
+    internal_monitor: String,
+    /// Display events received in this time span are dropped as duplicates.
+    suppressed: Option<(Instant, Instant)>,
+    lid_closed: Option<bool>,
+}
+
+impl EventFilter {
+    pub fn new(internal_monitor: String) -> Self {
+        Self {
+            internal_monitor,
+            suppressed: None,
+            lid_closed: None,
+        }
+    }
+
+    /// Decides by the time an event was `received`, not when it is looked at,
+    /// so events that queued up while another one was handled are judged correctly.
+    pub fn accept(&mut self, event: &Event, received: Instant) -> bool {
+        match event {
+            Event::LidState { closed } => {
+                self.lid_closed = Some(*closed);
+                false
+            }
+            // several sources report the same lid change
+            Event::LidClosed | Event::LidOpened => {
+                let closed = *event == Event::LidClosed;
+                let changed = self.lid_closed != Some(closed);
+                self.lid_closed = Some(closed);
+                changed
+            }
+            // the internal monitor only appears and disappears because of lid handling
+            Event::DisplayConnected {
+                name: Some(name), ..
+            }
+            | Event::DisplayDisconnected { name: Some(name) }
+                if *name == self.internal_monitor =>
+            {
+                false
+            }
+            Event::DisplayConnected { .. } | Event::DisplayDisconnected { .. } => self
+                .suppressed
+                .is_none_or(|(from, until)| received < from || received >= until),
+            _ => true,
+        }
+    }
+
+    /// Display events received shortly after an accepted one, which was received at
+    /// `accepted`, are duplicates. Later ones are real, even if they arrive while it is handled.
+    pub fn handled(&mut self, accepted: Instant) {
+        self.suppressed = Some((accepted, accepted + EVENT_DEDUP_WINDOW));
+    }
+
+    /// hyprdock changed the monitors itself since `started`, hyprland reports
+    /// these changes while it happens and shortly after.
+    pub fn changed_monitors(&mut self, started: Instant) {
+        self.suppressed = Some((started, Instant::now() + EVENT_DEDUP_WINDOW));
+    }
+}
+
+/// Replays a fixed list of events, used to test the daemon without hardware.
+#[cfg(test)]
+pub struct ScriptedSource {
+    pub events: Vec<Event>,
+}
+
+#[cfg(test)]
+impl EventSource for ScriptedSource {
+    fn name(&self) -> &'static str {
+        "scripted"
+    }
+
+    fn connect(&mut self) -> Result<(), SourceError> {
+        Ok(())
+    }
+
+    fn run(&mut self, sender: &Sender<Event>) -> Result<(), SourceError> {
+        for event in self.events.drain(..) {
+            sender.send(event)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn scripted_dispatch() {
+    let source = ScriptedSource {
+        events: vec![
+            Event::LidState { closed: false },
+            Event::DisplayConnected {
+                name: Some("DP-1".into()),
+                edid: None,
+            },
+            Event::DisplayConnected {
+                name: None,
+                edid: None,
+            },
+            Event::DisplayDisconnected {
+                name: Some("eDP-1".into()),
+            },
+            Event::LidClosed,
+            Event::LidClosed,
+            Event::PowerSourceChanged { on_ac: true },
+        ],
+    };
+    let receiver = start_sources(vec![Box::new(source)]).expect("Scripted source failed");
+    let mut filter = EventFilter::new("eDP-1".into());
+    let mut handled = Vec::new();
+    for event in receiver {
+        let received = Instant::now();
+        if filter.accept(&event, received) {
+            handled.push(event);
+            filter.handled(received);
+        }
+    }
+    assert_eq!(
+        handled,
+        vec![
+            Event::DisplayConnected {
+                name: Some("DP-1".into()),
+                edid: None,
+            },
+            Event::LidClosed,
+            Event::PowerSourceChanged { on_ac: true },
+        ]
+    );
+}
+
+#[test]
+fn initial_lid_state() {
+    let mut filter = EventFilter::new("eDP-1".into());
+    let now = Instant::now();
+    assert!(!filter.accept(&Event::LidState { closed: true }, now));
+    assert!(!filter.accept(&Event::LidClosed, now));
+    assert!(filter.accept(&Event::LidOpened, now));
+    assert!(filter.accept(&Event::LidClosed, now));
+}
+
+#[test]
+fn dedup_by_receive_time() {
+    let hotplug = Event::DisplayConnected {
+        name: Some("DP-1".into()),
+        edid: None,
+    };
+    let mut filter = EventFilter::new("eDP-1".into());
+    let accepted = Instant::now();
+    assert!(filter.accept(&hotplug, accepted));
+    filter.handled(accepted);
+    // looked at once handling finished, after the window, but received within it
+    assert!(!filter.accept(&hotplug, accepted + Duration::from_secs(1)));
+    // a real hotplug received while the first one was still handled
+    assert!(filter.accept(&hotplug, accepted + Duration::from_secs(5)));
+
+    let started = Instant::now();
+    filter.changed_monitors(started);
+    assert!(!filter.accept(&hotplug, Instant::now()));
+    assert!(filter.accept(&hotplug, Instant::now() + EVENT_DEDUP_WINDOW * 2));
+}
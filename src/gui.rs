@@ -15,11 +15,17 @@ You should have received a copy of the GNU Affero General Public License
 along with this program. If not, see <http://www.gnu.org/licenses/>.
 */
 
-use crate::{HyprDock, monitors::hypr_monitors::save_hypr_monitor_data};
-use gtk::{self, StyleContext, Window, gdk, glib::Propagation};
+use crate::{
+    HyprDock, inhibit::Inhibitor, monitors::hypr_monitors::save_hypr_monitor_data,
+    profiles::ProfileFormat,
+};
+use gtk::{
+    self, StyleContext, Window, gdk,
+    glib::{self, Propagation},
+};
 pub use gtk::{Button, prelude::*};
 use gtk_layer_shell::LayerShell;
-use std::rc::Rc;
+use std::{cell::Cell, rc::Rc};
 
 impl HyprDock {
     pub fn run_gui(&self) {
@@ -41,6 +47,7 @@ impl HyprDock {
             let app4 = apprc.clone();
             let app5 = apprc.clone();
             let app6 = apprc.clone();
+            let app7 = apprc.clone();
             let config_ref1 = dock2.clone();
             let config_ref2 = dock2.clone();
             let config_ref3 = dock2.clone();
@@ -88,33 +95,48 @@ impl HyprDock {
                 .margin_end(12)
                 .name("ExportButton")
                 .build();
+            let inhibit = Button::builder()
+                .label(inhibit_label())
+                .margin_top(12)
+                .margin_bottom(12)
+                .margin_start(12)
+                .margin_end(12)
+                .name("InhibitButton")
+                .build();
 
             external.connect_clicked(move |_external| {
-                config_ref1.external_monitor();
-                app1.quit();
+                run_confirmed(&app1, &config_ref1, HyprDock::external_monitor);
             });
             internal.connect_clicked(move |_internal| {
-                config_ref2.internal_monitor();
-                app2.quit();
+                run_confirmed(&app2, &config_ref2, HyprDock::internal_monitor);
             });
             extend.connect_clicked(move |_extend| {
-                config_ref3.extend_monitor();
-                app3.quit();
+                run_confirmed(&app3, &config_ref3, HyprDock::extend_monitor);
             });
             mirror.connect_clicked(move |_mirror| {
-                config_ref4.mirror_monitor();
-                app4.quit();
+                run_confirmed(&app4, &config_ref4, HyprDock::mirror_monitor);
             });
             export.connect_clicked(move |_mirror| {
-                save_hypr_monitor_data(config_ref5.monitor_config_path.clone(), None, None);
+                save_hypr_monitor_data(
+                    config_ref5.monitor_config_path.clone(),
+                    None,
+                    None,
+                    ProfileFormat::default(),
+                );
                 app5.quit();
             });
 
+            inhibit.connect_clicked(move |_inhibit| {
+                toggle_inhibit();
+                app7.quit();
+            });
+
             main_box.add(&internal);
             main_box.add(&external);
             main_box.add(&extend);
             main_box.add(&mirror);
             main_box.add(&export);
+            main_box.add(&inhibit);
 
             let window = Rc::new(
                 Window::builder()
@@ -135,27 +157,33 @@ impl HyprDock {
                     Propagation::Stop
                 }
                 gtk::gdk::keys::constants::_1 => {
-                    config_ref6.internal_monitor();
-                    app6.quit();
+                    run_confirmed(&app6, &config_ref6, HyprDock::internal_monitor);
                     Propagation::Stop
                 }
                 gtk::gdk::keys::constants::_2 => {
-                    config_ref6.external_monitor();
-                    app6.quit();
+                    run_confirmed(&app6, &config_ref6, HyprDock::external_monitor);
                     Propagation::Stop
                 }
                 gtk::gdk::keys::constants::_3 => {
-                    config_ref6.extend_monitor();
-                    app6.quit();
+                    run_confirmed(&app6, &config_ref6, HyprDock::extend_monitor);
                     Propagation::Stop
                 }
                 gtk::gdk::keys::constants::_4 => {
-                    config_ref6.mirror_monitor();
-                    app6.quit();
+                    run_confirmed(&app6, &config_ref6, HyprDock::mirror_monitor);
                     Propagation::Stop
                 }
                 gtk::gdk::keys::constants::_5 => {
-                    save_hypr_monitor_data(config_ref6.monitor_config_path.clone(), None, None);
+                    save_hypr_monitor_data(
+                        config_ref6.monitor_config_path.clone(),
+                        None,
+                        None,
+                        ProfileFormat::default(),
+                    );
+                    app6.quit();
+                    Propagation::Stop
+                }
+                gtk::gdk::keys::constants::_6 => {
+                    toggle_inhibit();
                     app6.quit();
                     Propagation::Stop
                 }
@@ -166,12 +194,104 @@ impl HyprDock {
         });
         app.run_with_args(&[""]);
     }
+    /// Asks whether to keep the current monitor layout.
+    /// Returns false if the user declines or does not answer within `confirm_timeout` seconds.
+    pub fn confirm_dialog(&self) -> bool {
+        if gtk::init().is_err() {
+            println!("Could not open confirmation dialog, keeping monitor configuration");
+            return true;
+        }
+        self.load_css();
+        let main_loop = glib::MainLoop::new(None, false);
+        let confirmed = Rc::new(Cell::new(false));
+        let remaining = Rc::new(Cell::new(self.confirm_timeout));
+
+        let label = gtk::Label::builder()
+            .label(confirm_text(remaining.get()))
+            .margin_top(12)
+            .margin_start(12)
+            .margin_end(12)
+            .name("ConfirmLabel")
+            .build();
+        let keep = Button::builder()
+            .label("Keep")
+            .margin_top(12)
+            .margin_bottom(12)
+            .margin_start(12)
+            .margin_end(12)
+            .name("KeepButton")
+            .build();
+        let revert = Button::builder()
+            .label("Revert")
+            .margin_top(12)
+            .margin_bottom(12)
+            .margin_start(12)
+            .margin_end(12)
+            .name("RevertButton")
+            .build();
+        let buttons = gtk::Box::builder().build();
+        buttons.add(&keep);
+        buttons.add(&revert);
+        let main_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .name("ConfirmBox")
+            .build();
+        main_box.add(&label);
+        main_box.add(&buttons);
+
+        let window = Window::builder()
+            .title("Monitor Portal")
+            .child(&main_box)
+            .name("ConfirmWindow")
+            .build();
+        window.init_layer_shell();
+        window.set_keyboard_interactivity(true);
+        window.set_layer(gtk_layer_shell::Layer::Overlay);
+
+        let (loop1, confirmed1) = (main_loop.clone(), confirmed.clone());
+        keep.connect_clicked(move |_keep| {
+            confirmed1.set(true);
+            loop1.quit();
+        });
+        let loop2 = main_loop.clone();
+        revert.connect_clicked(move |_revert| loop2.quit());
+        let (loop3, confirmed3) = (main_loop.clone(), confirmed.clone());
+        window.connect_key_press_event(move |_window, event| match event.keyval() {
+            gtk::gdk::keys::constants::Return => {
+                confirmed3.set(true);
+                loop3.quit();
+                Propagation::Stop
+            }
+            gtk::gdk::keys::constants::Escape => {
+                loop3.quit();
+                Propagation::Stop
+            }
+            _ => Propagation::Proceed,
+        });
+        let loop4 = main_loop.clone();
+        glib::timeout_add_seconds_local(1, move || {
+            let left = remaining.get().saturating_sub(1);
+            remaining.set(left);
+            label.set_label(&confirm_text(left));
+            if left == 0 {
+                loop4.quit();
+                return glib::ControlFlow::Break;
+            }
+            glib::ControlFlow::Continue
+        });
+
+        window.show_all();
+        main_loop.run();
+        window.close();
+        confirmed.get()
+    }
+
     fn load_css(&self) {
         let context_provider = gtk::CssProvider::new();
-        if self.css_string != "" {
+        if !self.css_string.is_empty() {
             context_provider
                 .load_from_path(&self.css_string)
-                .unwrap_or_else(|_| {});
+                .unwrap_or(());
         }
 
         StyleContext::add_provider_for_screen(
@@ -181,3 +301,42 @@ impl HyprDock {
         );
     }
 }
+
+fn confirm_text(seconds: u64) -> String {
+    format!(
+        "Keep this monitor configuration?\nReverting in {}s",
+        seconds
+    )
+}
+
+/// Hides the portal, so it does not cover the confirmation dialog.
+fn run_confirmed(app: &gtk::Application, dock: &HyprDock, action: fn(&HyprDock)) {
+    for window in app.windows() {
+        window.hide();
+    }
+    dock.confirmed(action);
+    app.quit();
+}
+
+fn inhibit_label() -> &'static str {
+    if crate::inhibit::is_inhibited() {
+        "Resume Docking"
+    } else {
+        "Inhibit Docking"
+    }
+}
+
+fn toggle_inhibit() {
+    let Some(inhibitor) = Inhibitor::new() else {
+        println!("XDG_RUNTIME_DIR is not set, can not inhibit automatic docking");
+        return;
+    };
+    let result = if inhibitor.is_inhibited() {
+        inhibitor.resume().map(|_| ())
+    } else {
+        inhibitor.inhibit(None).map(|_| ())
+    };
+    if let Err(err) = result {
+        println!("Could not toggle automatic docking: {}", err);
+    }
+}
@@ -0,0 +1,193 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    env, fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+const INHIBIT_FILE: &str = "hyprdock.inhibit";
+
+/// Stored in the runtime dir, so it is shared by the daemon, the CLI and the GUI
+/// and does not survive a reboot.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InhibitState {
+    /// Unix timestamp the inhibition ends at, `None` inhibits until resumed.
+    pub until: Option<u64>,
+}
+
+impl InhibitState {
+    /// Seconds until the inhibition ends, `None` if it does not end on its own.
+    pub fn remaining(&self) -> Option<u64> {
+        self.until.map(|until| until.saturating_sub(now()))
+    }
+
+    pub fn describe(&self) -> String {
+        match self.remaining() {
+            Some(remaining) => format!("inhibited for {}", format_duration(remaining)),
+            None => "inhibited until resumed".into(),
+        }
+    }
+}
+
+pub struct Inhibitor {
+    path: PathBuf,
+}
+
+impl Inhibitor {
+    pub fn new() -> Option<Self> {
+        env::var_os("XDG_RUNTIME_DIR")
+            .map(|dir| Self::with_path(PathBuf::from(dir).join(INHIBIT_FILE)))
+    }
+
+    pub fn with_path(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn inhibit(&self, duration: Option<Duration>) -> io::Result<InhibitState> {
+        let until = match duration {
+            Some(duration) => Some(now().checked_add(duration.as_secs()).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "duration is too long")
+            })?),
+            None => None,
+        };
+        let state = InhibitState { until };
+        fs::write(
+            &self.path,
+            serde_json::to_string(&state).map_err(io::Error::other)?,
+        )?;
+        Ok(state)
+    }
+
+    /// Returns whether automatic docking was inhibited.
+    pub fn resume(&self) -> io::Result<bool> {
+        match fs::remove_file(&self.path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The current inhibition, expired ones are removed.
+    pub fn state(&self) -> Option<InhibitState> {
+        let contents = fs::read_to_string(&self.path).ok()?;
+        let state: InhibitState = serde_json::from_str(&contents).unwrap_or_default();
+        if state.remaining() == Some(0) {
+            let _ = self.resume();
+            return None;
+        }
+        Some(state)
+    }
+
+    pub fn is_inhibited(&self) -> bool {
+        self.state().is_some()
+    }
+}
+
+/// Whether automatic docking is currently inhibited.
+pub fn is_inhibited() -> bool {
+    Inhibitor::new().is_some_and(|inhibitor| inhibitor.is_inhibited())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// Parses durations like `90s`, `30m`, `2h` or `1h30m`, plain numbers are seconds.
+/// A number without a unit is only accepted on its own, `1h30` is rejected.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let mut total = 0;
+    let mut units = false;
+    let mut number = String::new();
+    for c in input.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => return None,
+        };
+        let secs = number.parse::<u64>().ok()?.checked_mul(unit)?;
+        total = secs.checked_add(total)?;
+        units = true;
+        number.clear();
+    }
+    if !number.is_empty() {
+        if units {
+            return None;
+        }
+        total = number.parse::<u64>().ok()?;
+    }
+    if total == 0 {
+        return None;
+    }
+    Some(Duration::from_secs(total))
+}
+
+fn format_duration(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs.div_ceil(60)),
+        _ => format!("{}h{}m", secs / 3600, (secs % 3600) / 60),
+    }
+}
+
+#[test]
+fn inhibit_durations() {
+    assert_eq!(parse_duration("30m"), Some(Duration::from_secs(1800)));
+    assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5400)));
+    assert_eq!(parse_duration("45"), Some(Duration::from_secs(45)));
+    assert_eq!(parse_duration(""), None);
+    assert_eq!(parse_duration("5x"), None);
+    assert_eq!(parse_duration("m"), None);
+    assert_eq!(parse_duration("0"), None);
+    assert_eq!(parse_duration("0m"), None);
+    assert_eq!(parse_duration("1h30"), None);
+    assert_eq!(parse_duration("18446744073709551615h"), None);
+    assert_eq!(parse_duration("18446744073709551615s1s"), None);
+    assert_eq!(parse_duration("99999999999999999999"), None);
+}
+
+#[test]
+fn inhibit_state() {
+    let path = env::temp_dir().join(format!("hyprdock-inhibit-{}", std::process::id()));
+    let inhibitor = Inhibitor::with_path(path);
+    let _ = inhibitor.resume();
+    assert!(!inhibitor.is_inhibited());
+
+    inhibitor.inhibit(None).unwrap();
+    assert_eq!(inhibitor.state(), Some(InhibitState { until: None }));
+    inhibitor.inhibit(Some(Duration::from_secs(1800))).unwrap();
+    assert_eq!(inhibitor.state().unwrap().describe(), "inhibited for 30m");
+
+    inhibitor
+        .inhibit(Some(Duration::ZERO))
+        .expect("Could not write inhibit state");
+    assert!(!inhibitor.is_inhibited());
+    assert!(!inhibitor.resume().unwrap());
+    assert!(inhibitor.inhibit(Some(Duration::MAX)).is_err());
+}
@@ -0,0 +1,162 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How hyprdock decides that the locker is up and the system can be suspended.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "probe", rename_all = "snake_case")]
+pub enum ReadinessProbe {
+    /// Ready as soon as the locker was started.
+    None,
+    /// Ready after the given time.
+    Delay { ms: u64 },
+    /// Ready once the file exists, for lockers that can signal readiness with a file.
+    File { path: String },
+    /// Ready once `pgrep <pattern>` finds a process other than the started locker,
+    /// e.g. the one `swaylock -f` forks once the screen is locked.
+    Pgrep { pattern: String },
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct LockReadiness {
+    #[serde(flatten)]
+    pub probe: ReadinessProbe,
+    /// Seconds to wait for the probe, suspend happens afterwards regardless.
+    #[serde(default = "default_max_wait")]
+    pub max_wait: u64,
+}
+
+fn default_max_wait() -> u64 {
+    5
+}
+
+impl Default for LockReadiness {
+    fn default() -> Self {
+        Self {
+            probe: ReadinessProbe::Delay { ms: 1000 },
+            max_wait: default_max_wait(),
+        }
+    }
+}
+
+impl ReadinessProbe {
+    fn is_ready(&self, started: Instant, locker: u32) -> bool {
+        match self {
+            ReadinessProbe::None => true,
+            ReadinessProbe::Delay { ms } => started.elapsed() >= Duration::from_millis(*ms),
+            ReadinessProbe::File { path } => Path::new(path).exists(),
+            // the locker itself matches right away, long before it is ready
+            ReadinessProbe::Pgrep { pattern } => Command::new("pgrep")
+                .arg(pattern)
+                .stderr(Stdio::null())
+                .output()
+                .is_ok_and(|output| {
+                    String::from_utf8_lossy(&output.stdout)
+                        .lines()
+                        .any(|pid| pid.trim().parse() != Ok(locker))
+                }),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LockerState {
+    Ready,
+    /// The locker is running, but the probe did not report readiness within `max_wait`.
+    NotReady,
+    /// The locker failed before it was ready.
+    Failed(ExitStatus),
+}
+
+impl LockReadiness {
+    /// Waits until the locker is alive and the probe reports readiness.
+    /// Lockers that fork into the background, like `swaylock -f`, exit successfully
+    /// while staying up, for these only the probe is checked.
+    pub fn wait(&self, locker: &mut Child) -> LockerState {
+        let started = Instant::now();
+        let deadline = started + Duration::from_secs(self.max_wait);
+        loop {
+            if let Ok(Some(status)) = locker.try_wait()
+                && !status.success()
+            {
+                return LockerState::Failed(status);
+            }
+            if self.probe.is_ready(started, locker.id()) {
+                return LockerState::Ready;
+            }
+            if Instant::now() >= deadline {
+                return LockerState::NotReady;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[test]
+fn lock_readiness_probes() {
+    let spawn = |script: &str| {
+        Command::new("sh")
+            .args(["-c", script])
+            .spawn()
+            .expect("Could not start fake locker")
+    };
+    let file = std::env::temp_dir().join(format!("hyprdock-locked-{}", std::process::id()));
+    let _ = std::fs::remove_file(&file);
+    let readiness = |probe| LockReadiness { probe, max_wait: 5 };
+
+    let mut locker = spawn(&format!("sleep 0.2; touch {}; sleep 5", file.display()));
+    let probe = readiness(ReadinessProbe::File {
+        path: file.to_str().unwrap().into(),
+    });
+    assert_eq!(probe.wait(&mut locker), LockerState::Ready);
+    let _ = locker.kill();
+    let _ = std::fs::remove_file(&file);
+
+    let mut locker = spawn("exit 1");
+    assert!(matches!(probe.wait(&mut locker), LockerState::Failed(_)));
+
+    let mut locker = spawn("sleep 5");
+    let start = Instant::now();
+    let probe = LockReadiness {
+        probe: ReadinessProbe::Pgrep {
+            pattern: "hyprdock-no-such-locker".into(),
+        },
+        max_wait: 0,
+    };
+    assert_eq!(probe.wait(&mut locker), LockerState::NotReady);
+    assert!(readiness(ReadinessProbe::Delay { ms: 0 }).wait(&mut locker) == LockerState::Ready);
+    assert!(start.elapsed() < Duration::from_secs(5));
+    let _ = locker.kill();
+    let _ = locker.wait();
+}
+
+#[test]
+fn lock_readiness_default_max_wait() {
+    let readiness: LockReadiness = toml::from_str("probe = \"delay\"\nms = 500").unwrap();
+    assert_eq!(readiness.probe, ReadinessProbe::Delay { ms: 500 });
+    assert_eq!(readiness.max_wait, 5);
+}
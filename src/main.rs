@@ -15,31 +15,66 @@ You should have received a copy of the GNU Affero General Public License
 along with this program. If not, see <http://www.gnu.org/licenses/>.
 */
 
+use command::run_command;
+use config::{Diagnostic, default_config, load_config, reload_config};
+use control::{ControlRequest, ControlResponse, DaemonMessage, DaemonStatus};
 use directories_next as dirs;
+use events::{Event, EventFilter};
+use inhibit::Inhibitor;
+use lock::{LockReadiness, LockerState};
 use monitors::hypr_monitors::{
-    get_all_hypr_monitors, get_current_monitor_hash, save_hypr_monitor_data,
-    set_hypr_monitors_from_file, try_get_monitor_hash_path,
+    find_matching_profile, get_all_hypr_monitors, leave_active_profile, migrate_monitor_configs,
+    save_hypr_monitor_data, set_hypr_monitors_from_file,
+};
+use monitors::{
+    edid,
+    hypr_ipc::HyprClient,
+    snapshot::{ConfirmOutcome, confirm_or_revert},
 };
 use once_cell::sync::Lazy;
-use optional_struct::{Applicable, optional_struct};
+use optional_struct::optional_struct;
+use profiles::{ProfileFormat, ProfileStore, run_profile_command};
 use serde::{Deserialize, Serialize};
 use std::{
-    env, fs,
-    io::Read,
-    os::unix::net::UnixStream,
-    path::PathBuf,
-    process::{Command, ExitCode},
+    cell::OnceCell,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, Stdio},
+    sync::{Arc, mpsc},
     thread,
+    time::{Duration, Instant},
 };
-use toml;
+use template::{Placeholder, TemplateError, expand_env, expand_placeholders, shell_quote};
+use watch::{ConfigChange, ConfigWatcher};
 
+pub mod command;
+pub mod config;
+pub mod control;
+pub mod events;
 pub mod gui;
+pub mod inhibit;
+pub mod lock;
 pub mod monitors;
+pub mod profiles;
+pub mod status;
+pub mod template;
+pub mod watch;
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 struct HyprdockCommand {
+    #[serde(default)]
     base: String,
+    #[serde(default)]
     args: Vec<String>,
+    /// Run with `sh -c` instead of base and args, for commands that need pipes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shell: Option<String>,
+    /// Do not wait for the command, for programs that keep running like bars or lockers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    background: Option<bool>,
+    /// Seconds to wait for the command before it is killed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout: Option<u64>,
 }
 
 impl HyprdockCommand {
@@ -47,42 +82,71 @@ impl HyprdockCommand {
         Self {
             base: "".into(),
             args: Vec::new(),
+            shell: None,
+            background: None,
+            timeout: None,
         }
     }
     pub fn new(base: &'static str, args: &[&str]) -> Self {
         Self {
             base: base.trim().into(),
             args: args
-                .into_iter()
+                .iter()
                 .map(|val| String::from(*val))
                 .collect::<Vec<String>>(),
+            shell: None,
+            background: None,
+            timeout: None,
         }
     }
     pub fn single(base: &'static str) -> Self {
         Self {
             base: base.trim().into(),
             args: Vec::new(),
+            shell: None,
+            background: None,
+            timeout: None,
         }
     }
 
-    pub fn format(&self, monitor: &String) -> Self {
-        let mut new_args = Vec::new();
-        for arg in self.args.iter() {
-            let processed_arg = arg.replace("{}", &monitor);
-            new_args.push(processed_arg);
-        }
-        Self {
-            base: self.base.clone(),
-            args: new_args,
+    /// Fills in placeholders and environment variables, a shell command becomes `sh -c`.
+    /// Environment variables in shell commands are left to the shell, elsewhere they are
+    /// expanded first, so a `$` in a placeholder value is kept as is.
+    pub fn format(&self, lookup: impl Fn(Placeholder) -> String) -> Result<Self, TemplateError> {
+        if let Some(shell) = self.shell.as_ref().filter(|shell| !shell.trim().is_empty()) {
+            return Ok(Self {
+                base: "sh".into(),
+                // values like the profile name are chosen by the user, they must not be run
+                args: vec![
+                    "-c".into(),
+                    expand_placeholders(shell, |placeholder| shell_quote(&lookup(placeholder)))?,
+                ],
+                shell: None,
+                ..self.clone()
+            });
         }
+        let base = expand_placeholders(&expand_env(&self.base), &lookup)?;
+        let args = self
+            .args
+            .iter()
+            .map(|arg| expand_placeholders(&expand_env(arg), &lookup))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            base,
+            args,
+            ..self.clone()
+        })
     }
 }
 
-const DEFAULT_CONFIG: Lazy<OptionalHyprDock> = Lazy::new(|| {
+static DEFAULT_CONFIG: Lazy<OptionalHyprDock> = Lazy::new(|| {
     let fetcher = "hyprctl";
     OptionalHyprDock {
         monitor_name: Some("eDP-1".into()),
         default_external_mode: Some("extend".into()),
+        event_sources: Some(vec!["acpid".into(), "hyprland".into()]),
+        confirm_timeout: Some(15),
+        lock_readiness: Some(LockReadiness::default()),
         init_command: Some(HyprdockCommand::empty()),
         open_bar_command: Some(HyprdockCommand::empty()),
         close_bar_command: Some(HyprdockCommand::empty()),
@@ -127,15 +191,16 @@ const DEFAULT_CONFIG: Lazy<OptionalHyprDock> = Lazy::new(|| {
     }
 });
 
-fn default_config_string() -> String {
-    toml::to_string(&DEFAULT_CONFIG.to_owned()).unwrap()
-}
-
 #[optional_struct]
 #[derive(Deserialize, Serialize, Clone)]
 struct HyprDock {
     monitor_name: String,
     default_external_mode: String,
+    event_sources: Vec<String>,
+    /// Seconds to confirm a changed monitor layout before it is reverted, 0 disables it.
+    confirm_timeout: u64,
+    /// When the locker counts as ready, suspend only happens afterwards.
+    lock_readiness: LockReadiness,
     css_string: String,
     monitor_config_path: String,
     init_command: HyprdockCommand,
@@ -162,60 +227,107 @@ fn main() -> ExitCode {
         return ExitCode::FAILURE;
     }
 
-    let dock = parse_config(
-        create_config_dir()
-            .map(|path| path.join("hyprdock.toml"))
-            .unwrap_or_default()
-            .to_str()
-            .expect("Could not convert path to string"),
-    );
+    let path = config_path();
+    let (dock, diagnostics) = load_config(&path);
+    let usable = dock.is_some();
+    let reports_config = matches!(args[1].as_str(), "config" | "--server" | "-s");
+    if !reports_config && diagnostics.iter().any(Diagnostic::is_error) {
+        eprintln!(
+            "{} has errors, {}. Run hyprdock config check for details",
+            path,
+            fallback(usable)
+        );
+    }
+    let mut dock = dock.unwrap_or_else(default_config);
 
-    let mut iter = args.iter();
-    iter.next();
-    let mut iteration = 0;
-    for _ in 0..args.len() - 1 {
-        if iteration == args.len() - 1 {
-            break;
-        }
-        iteration += 1;
-        match iter.next().unwrap().as_str() {
-            "--internal" | "-i" => dock.internal_monitor(),
-            "--external" | "-e" => dock.external_monitor(),
-            "--extend" | "-eo" => dock.extend_monitor(),
-            "--mirror" | "-io" => dock.mirror_monitor(),
+    migrate_monitor_configs(&dock.monitor_config_path);
+
+    let mut iter = args.iter().skip(1).peekable();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--internal" | "-i" => apply_mode(&dock, "internal", HyprDock::internal_monitor),
+            "--external" | "-e" => apply_mode(&dock, "external", HyprDock::external_monitor),
+            "--extend" | "-eo" => apply_mode(&dock, "extend", HyprDock::extend_monitor),
+            "--mirror" | "-io" => apply_mode(&dock, "mirror", HyprDock::mirror_monitor),
             "--suspend" | "-su" => dock.lock_system(),
             "--utility" | "-u" => dock.utility(),
             "--wallpaper" | "-w" => dock.wallpaper(),
             "--export" | "-ex" => {
-                let next_token = iter.next();
-                if next_token.is_none() {
-                    save_hypr_monitor_data(dock.monitor_config_path.clone(), None, None);
-                    return ExitCode::SUCCESS;
+                let name = iter.next_if(|token| !token.starts_with('-'));
+                let mut format = ProfileFormat::default();
+                if iter.next_if(|token| *token == "--format").is_some() {
+                    match iter
+                        .next()
+                        .and_then(|token| ProfileFormat::from_name(token))
+                    {
+                        Some(requested) => format = requested,
+                        None => {
+                            print_help();
+                            return ExitCode::FAILURE;
+                        }
+                    }
                 }
-                if next_token.unwrap().chars().next().unwrap() == '-' {
+                save_hypr_monitor_data(dock.monitor_config_path.clone(), name, None, format);
+            }
+            "--import" | "-in" => {
+                let name = iter.next();
+                if name.is_some_and(|name| name.starts_with('-')) {
                     print_help();
                     return ExitCode::FAILURE;
                 }
-                save_hypr_monitor_data(dock.monitor_config_path.clone(), next_token, None);
-                iteration += 1;
+                let request = ControlRequest::Import {
+                    profile: name.cloned(),
+                };
+                if !forward_to_daemon(&request) {
+                    dock.confirmed(|dock| dock.import_profile(name));
+                }
             }
-            "--import" | "-in" => {
-                let next_token = iter.next();
-                if next_token.is_none() {
-                    set_hypr_monitors_from_file(dock.monitor_config_path.clone(), None, None);
-                    return ExitCode::SUCCESS;
+            "daemon" => return run_daemon_command(iter.next().map(|arg| arg.as_str())),
+            "inhibit" => {
+                let mut duration = None;
+                if iter.next_if(|token| *token == "--for").is_some() {
+                    duration = iter.next().and_then(|token| inhibit::parse_duration(token));
+                    if duration.is_none() {
+                        print_help();
+                        return ExitCode::FAILURE;
+                    }
+                }
+                return print_result(inhibit(duration));
+            }
+            "resume" => return print_result(resume()),
+            "status" => {
+                let status = dock.status();
+                if iter.next_if(|token| *token == "--json").is_some() {
+                    match serde_json::to_string(&status) {
+                        Ok(json) => println!("{}", json),
+                        Err(err) => println!("Could not serialize status: {}", err),
+                    }
+                } else {
+                    println!("{}", status);
                 }
-                if next_token.unwrap().chars().next().unwrap() == '-' {
+                return ExitCode::SUCCESS;
+            }
+            "profile" => {
+                return run_profile_command(&dock.monitor_config_path, &iter.collect::<Vec<_>>());
+            }
+            "config" => {
+                if iter.next().is_none_or(|token| token != "check") {
                     print_help();
                     return ExitCode::FAILURE;
                 }
-                set_hypr_monitors_from_file(dock.monitor_config_path.clone(), next_token, None);
-                iteration += 1;
-                dock.wallpaper();
-                dock.reload_bar();
-                dock.fix_bar();
+                return check_config(&path, usable, &diagnostics);
+            }
+            "--server" | "-s" => {
+                log_diagnostics(&path, &diagnostics);
+                if diagnostics.iter().any(Diagnostic::is_error) {
+                    println!(
+                        "{} has errors, {} until they are fixed",
+                        path,
+                        fallback(usable)
+                    );
+                }
+                dock.socket_connect();
             }
-            "--server" | "-s" => dock.socket_connect(),
             "--version" | "-v" => println!("{}", env!("CARGO_PKG_VERSION")),
             "--help" | "-h" => {
                 print_help();
@@ -232,6 +344,125 @@ fn main() -> ExitCode {
     ExitCode::SUCCESS
 }
 
+fn config_path() -> String {
+    create_config_dir()
+        .map(|path| path.join("hyprdock.toml"))
+        .unwrap_or_default()
+        .to_str()
+        .expect("Could not convert path to string")
+        .to_string()
+}
+
+/// Lets a running daemon handle the request, so the CLI and the daemon
+/// never change monitors at the same time. Returns false if no daemon is running.
+fn forward_to_daemon(request: &ControlRequest) -> bool {
+    let Some(response) = control::forward(request) else {
+        return false;
+    };
+    println!("{}", response.message);
+    true
+}
+
+fn apply_mode(dock: &HyprDock, mode: &str, action: fn(&HyprDock)) {
+    let request = ControlRequest::Apply { mode: mode.into() };
+    if !forward_to_daemon(&request) {
+        dock.confirmed(action);
+    }
+}
+
+fn inhibit(duration: Option<Duration>) -> Result<String, String> {
+    let inhibitor = Inhibitor::new().ok_or("XDG_RUNTIME_DIR is not set")?;
+    match inhibitor.inhibit(duration) {
+        Ok(state) => Ok(format!("Automatic docking {}", state.describe())),
+        Err(err) => Err(format!("Could not inhibit automatic docking: {}", err)),
+    }
+}
+
+fn resume() -> Result<String, String> {
+    let inhibitor = Inhibitor::new().ok_or("XDG_RUNTIME_DIR is not set")?;
+    match inhibitor.resume() {
+        Ok(true) => Ok("Automatic docking resumed".into()),
+        Ok(false) => Ok("Automatic docking was not inhibited".into()),
+        Err(err) => Err(format!("Could not resume automatic docking: {}", err)),
+    }
+}
+
+fn print_result(result: Result<String, String>) -> ExitCode {
+    match result {
+        Ok(message) => {
+            println!("{}", message);
+            ExitCode::SUCCESS
+        }
+        Err(message) => {
+            println!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn log_diagnostics(path: &str, diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        println!("{}: {}", path, diagnostic);
+    }
+}
+
+/// What hyprdock does about a config with errors.
+fn fallback(usable: bool) -> &'static str {
+    if usable {
+        "using the defaults for the invalid settings"
+    } else {
+        "using the default config"
+    }
+}
+
+/// Handles `hyprdock config check`, fails if the config has errors.
+fn check_config(path: &str, usable: bool, diagnostics: &[Diagnostic]) -> ExitCode {
+    if diagnostics.is_empty() {
+        println!("{} is valid", path);
+        return ExitCode::SUCCESS;
+    }
+    log_diagnostics(path, diagnostics);
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        println!(
+            "hyprdock is {} until the errors are fixed",
+            fallback(usable)
+        );
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Handles `hyprdock daemon <command>`.
+fn run_daemon_command(command: Option<&str>) -> ExitCode {
+    let request = match command {
+        Some("status") => ControlRequest::Status,
+        Some("reload") => ControlRequest::Reload,
+        Some("pause") => ControlRequest::Pause,
+        Some("resume") => ControlRequest::Resume,
+        _ => {
+            print_help();
+            return ExitCode::FAILURE;
+        }
+    };
+    let Some(response) = control::forward(&request) else {
+        println!("hyprdock daemon is not running");
+        return ExitCode::FAILURE;
+    };
+    println!("{}", response.message);
+    if let Some(status) = response.status {
+        println!(
+            "active profile: {}",
+            status.active_profile.as_deref().unwrap_or("none")
+        );
+    }
+    if response.ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
 fn create_config_dir() -> Result<PathBuf, std::io::Error> {
     let maybe_config_dir = dirs::ProjectDirs::from("com", "Xetibo", "hyprdock");
     if maybe_config_dir.is_none() {
@@ -270,9 +501,23 @@ fn print_help() {
             --export/-ex:   Export current monitor config
                             optional name for import
                             usage: hyprdock --export configname OR hyprdock --export
+                            --format toml saves a hand editable profile
+                            usage: hyprdock --export configname --format toml
             --import/-in:   Import a monitor config
                             optional name for import 
                             usage: hyprdock --import configname OR hyprdock --import
+            profile:        Manage saved monitor configs
+                            usage: hyprdock profile list|show|rename|copy|delete
+            config:         Check the config for errors
+                            usage: hyprdock config check
+            daemon:         Control the running daemon
+                            usage: hyprdock daemon status|reload|pause|resume
+            inhibit:        Stop reacting to lid and monitor events
+                            optional duration, e.g. 30m, 1h30m
+                            usage: hyprdock inhibit OR hyprdock inhibit --for 30m
+            resume:         React to lid and monitor events again
+            status:         Show lid, monitors, profile and daemon state
+                            usage: hyprdock status OR hyprdock status --json
             --server/-s:    daemon version
                             automatically handles actions on laptop lid close and open.
             --gui/-g:       Launch GUI version
@@ -281,47 +526,128 @@ fn print_help() {
     );
 }
 
-fn parse_config(path: &str) -> HyprDock {
-    let contents = match fs::read_to_string(path) {
-        Ok(c) => c,
-        Err(_) => default_config_string(),
-    };
-    let parsed_conf: OptionalHyprDock = match toml::from_str(&contents) {
-        Ok(d) => d,
-        Err(_) => DEFAULT_CONFIG.to_owned(),
-    };
-    parsed_conf.build(DEFAULT_CONFIG.to_owned().try_into().unwrap())
+/// The profile for the connected monitors, if any.
+/// Returns `None` if the monitors could not be queried at all.
+fn current_profile(base_path: &str) -> Option<Option<String>> {
+    find_matching_profile(base_path.to_string())
+        .map_err(|err| println!("Could not get current monitors: {}", err))
+        .ok()
+}
+
+fn current_monitors() -> Vec<monitors::Monitor> {
+    get_all_hypr_monitors().unwrap_or_else(|err| {
+        println!("Could not get current monitors: {}", err);
+        Vec::new()
+    })
 }
 
 impl HyprDock {
-    pub fn execute_command(&self, command: HyprdockCommand) {
-        let base = command.base.trim().to_string();
+    /// The configured commands with their config keys.
+    fn commands(&self) -> [(&'static str, &HyprdockCommand); 15] {
+        [
+            ("init_command", &self.init_command),
+            ("open_bar_command", &self.open_bar_command),
+            ("close_bar_command", &self.close_bar_command),
+            ("reload_bar_command", &self.reload_bar_command),
+            ("suspend_command", &self.suspend_command),
+            ("lock_command", &self.lock_command),
+            ("utility_command", &self.utility_command),
+            ("get_monitors_command", &self.get_monitors_command),
+            (
+                "enable_internal_monitor_command",
+                &self.enable_internal_monitor_command,
+            ),
+            (
+                "disable_internal_monitor_command",
+                &self.disable_internal_monitor_command,
+            ),
+            (
+                "enable_external_monitor_command",
+                &self.enable_external_monitor_command,
+            ),
+            (
+                "disable_external_monitor_command",
+                &self.disable_external_monitor_command,
+            ),
+            ("extend_command", &self.extend_command),
+            ("mirror_command", &self.mirror_command),
+            ("wallpaper_command", &self.wallpaper_command),
+        ]
+    }
+
+    /// Fills in the placeholders of the command, monitors are only queried if they are used.
+    fn format_command(&self, command: &HyprdockCommand) -> HyprdockCommand {
+        let cached = OnceCell::new();
+        let monitors = || cached.get_or_init(current_monitors);
+        let externals = || {
+            monitors()
+                .iter()
+                .filter(|monitor| !monitor.disabled && monitor.name != self.monitor_name)
+                .map(|monitor| monitor.name.clone())
+                .collect::<Vec<String>>()
+        };
+        command
+            .format(|placeholder| match placeholder {
+                Placeholder::Internal => self.monitor_name.clone(),
+                Placeholder::Externals => externals().join(" "),
+                Placeholder::FirstExternal => externals().into_iter().next().unwrap_or_default(),
+                Placeholder::Profile => ProfileStore::new(&self.monitor_config_path)
+                    .active()
+                    .unwrap_or_default(),
+                Placeholder::Mode => status::dock_mode(monitors(), &self.monitor_name).to_string(),
+                Placeholder::Lid => status::lid_state().to_string(),
+            })
+            .unwrap_or_else(|err| {
+                println!("Command {} not run: {}", command.base, err);
+                HyprdockCommand::empty()
+            })
+    }
+
+    /// Runs the command and waits for it to finish, so actions happen in order.
+    pub fn execute_command(&self, command: &HyprdockCommand) -> bool {
+        self.run_command(command, false)
+    }
+
+    /// Like `execute_command`, but does not wait unless `background = false` is set,
+    /// for commands that usually keep running.
+    pub fn execute_long_running(&self, command: &HyprdockCommand) -> bool {
+        self.run_command(command, true)
+    }
+
+    fn run_command(&self, command: &HyprdockCommand, background_by_default: bool) -> bool {
+        let command = self.format_command(command);
+        let base = command.base.trim();
         if base.is_empty() {
-            return;
+            return true;
         }
-        thread::spawn(move || {
-            Command::new(base)
-                .args(command.args)
-                .spawn()
-                .expect("Could not parse command, please check your toml");
-        });
+        let outcome = run_command(
+            base,
+            &command.args,
+            command.background.unwrap_or(background_by_default),
+            command
+                .timeout
+                .map_or(command::DEFAULT_TIMEOUT, Duration::from_secs),
+        );
+        if !outcome.success() {
+            println!("Command {} failed: {}", base, outcome);
+        }
+        outcome.success()
     }
 
     pub fn handle_close(&self) {
         if self.has_external_monitor() {
-            self.execute_command(
-                self.disable_internal_monitor_command
-                    .format(&self.monitor_name),
-            );
-            let monitor_hash = get_current_monitor_hash(None);
-            let path = try_get_monitor_hash_path(self.monitor_config_path.clone(), &monitor_hash);
-            if path.is_some() {
+            self.execute_command(&self.disable_internal_monitor_command);
+            let Some(profile) = current_profile(&self.monitor_config_path) else {
+                return;
+            };
+            if profile.is_some() {
                 set_hypr_monitors_from_file(
                     self.monitor_config_path.clone(),
                     None,
-                    Some(&monitor_hash),
+                    profile.as_ref(),
                 );
             } else {
+                leave_active_profile(self.monitor_config_path.clone());
                 self.external_monitor();
             }
             self.wallpaper();
@@ -333,22 +659,17 @@ impl HyprDock {
     }
 
     pub fn handle_open(&self) {
-        let monitor_hash = get_current_monitor_hash(None);
+        let Some(profile) = current_profile(&self.monitor_config_path) else {
+            return;
+        };
         if self.is_internal_active() {
             return;
         }
-        self.execute_command(
-            self.enable_internal_monitor_command
-                .format(&self.monitor_name),
-        );
-        let path = try_get_monitor_hash_path(self.monitor_config_path.clone(), &monitor_hash);
-        if path.is_some() {
-            set_hypr_monitors_from_file(
-                self.monitor_config_path.clone(),
-                None,
-                Some(&monitor_hash),
-            );
+        self.execute_command(&self.enable_internal_monitor_command);
+        if profile.is_some() {
+            set_hypr_monitors_from_file(self.monitor_config_path.clone(), None, profile.as_ref());
         } else {
+            leave_active_profile(self.monitor_config_path.clone());
             self.add_monitor();
         }
         self.wallpaper();
@@ -356,107 +677,341 @@ impl HyprDock {
         self.fix_bar();
     }
 
-    pub fn handle_event(&self, event: &str) {
+    /// Runs an action, the resulting monitor layout has to be confirmed or it is reverted.
+    pub fn confirmed(&self, action: impl FnOnce(&Self)) {
+        if self.confirm_timeout == 0 {
+            action(self);
+            return;
+        }
+        let client = match HyprClient::new() {
+            Ok(client) => client,
+            Err(err) => {
+                println!("Could not snapshot monitors: {}", err);
+                action(self);
+                return;
+            }
+        };
+        match confirm_or_revert(&client, || action(self), || self.confirm_dialog()) {
+            Ok(ConfirmOutcome::Reverted) => {
+                println!("Monitor configuration was not confirmed, reverted");
+                self.wallpaper();
+                self.reload_bar();
+            }
+            Ok(ConfirmOutcome::Kept(reason)) => println!(
+                "Monitor configuration was not confirmed, but it is kept because the previous one can not be restored: {}",
+                reason
+            ),
+            Ok(_) => (),
+            Err(err) => println!("Could not confirm monitor configuration: {}", err),
+        }
+    }
+
+    pub fn handle_event(&self, event: &Event) {
+        // hyprland may not identify a monitor, profile matching falls back to its EDID
+        match event {
+            Event::DisplayConnected {
+                name: Some(name),
+                edid,
+            } => edid::remember(name, edid.as_ref()),
+            Event::DisplayDisconnected { name: Some(name) } => edid::remember(name, None),
+            _ => (),
+        }
+        let docking_event = matches!(
+            event,
+            Event::LidClosed
+                | Event::LidOpened
+                | Event::DisplayConnected { .. }
+                | Event::DisplayDisconnected { .. }
+        );
+        if docking_event && inhibit::is_inhibited() {
+            println!("Automatic docking is inhibited, ignoring {:?}", event);
+            return;
+        }
+        // automatic changes are not confirmed, reverting them could enable
+        // monitors behind a closed lid or ones that were just unplugged
+        self.handle_monitor_event(event);
+    }
+
+    fn handle_monitor_event(&self, event: &Event) {
         match event {
-            _ if event.contains("LID close") => self.handle_close(),
-            _ if event.contains("LID open") => self.handle_open(),
-            _ if event.contains("VIDEOOUT plug") => {
-                let monitor_hash = get_current_monitor_hash(None);
-                let path =
-                    try_get_monitor_hash_path(self.monitor_config_path.clone(), &monitor_hash);
-                if path.is_none() {
+            Event::LidClosed => self.handle_close(),
+            Event::LidOpened => self.handle_open(),
+            Event::DisplayConnected { .. } => {
+                let Some(profile) = current_profile(&self.monitor_config_path) else {
+                    return;
+                };
+                if profile.is_none() {
+                    leave_active_profile(self.monitor_config_path.clone());
                     self.add_monitor();
-                    save_hypr_monitor_data(self.monitor_config_path.clone(), None, None);
+                    save_hypr_monitor_data(
+                        self.monitor_config_path.clone(),
+                        None,
+                        None,
+                        ProfileFormat::default(),
+                    );
                 } else {
                     set_hypr_monitors_from_file(
                         self.monitor_config_path.clone(),
                         None,
-                        Some(&monitor_hash),
+                        profile.as_ref(),
                     );
                 }
                 self.wallpaper();
                 self.reload_bar();
                 self.fix_bar();
             }
-            _ if event.contains("VIDEOOUT unplug") => {
-                let monitor_hash = get_current_monitor_hash(None);
-                let path =
-                    try_get_monitor_hash_path(self.monitor_config_path.clone(), &monitor_hash);
-                if path.is_some() {
+            Event::DisplayDisconnected { .. } => {
+                let Some(profile) = current_profile(&self.monitor_config_path) else {
+                    return;
+                };
+                if profile.is_some() {
                     set_hypr_monitors_from_file(
                         self.monitor_config_path.clone(),
                         None,
-                        Some(&monitor_hash),
+                        profile.as_ref(),
                     );
                     return;
                 }
+                leave_active_profile(self.monitor_config_path.clone());
                 self.internal_monitor();
-                set_hypr_monitors_from_file(
-                    self.monitor_config_path.clone(),
-                    None,
-                    Some(&monitor_hash),
-                );
             }
-            _ => {}
+            Event::LidState { .. }
+            | Event::PowerSourceChanged { .. }
+            | Event::TabletModeChanged { .. } => {}
         }
     }
 
-    pub fn socket_connect(&self) {
+    pub fn socket_connect(&mut self) {
         self.init();
-        let sock = UnixStream::connect("/var/run/acpid.socket");
-        if sock.is_err() {
-            println!(
-                "Could not connect to acpid socket, do you have the service installed and running?"
-            );
+        let Some(events) = events::start_sources(events::sources_from_config(&self.event_sources))
+        else {
+            println!("No event source could be started, please check event_sources in your toml");
+            return;
+        };
+        let (sender, receiver) = mpsc::channel();
+        control::forward_events(events, sender.clone());
+        match control::socket_path().map(|path| control::bind(&path)) {
+            Some(Ok(listener)) => control::serve(listener, sender.clone()),
+            Some(Err(err)) if err.kind() == io::ErrorKind::AddrInUse => {
+                println!("hyprdock is already running: {}", err);
+                return;
+            }
+            Some(Err(err)) => println!("Could not open control socket: {}", err),
+            None => println!("XDG_RUNTIME_DIR is not set, control socket disabled"),
+        }
+
+        let profiles = ProfileStore::new(&self.monitor_config_path);
+        let watcher = match ConfigWatcher::new(Path::new(&config_path()), profiles.dir()) {
+            Ok(watcher) => {
+                let watcher = Arc::new(watcher);
+                watch::watch_config(watcher.clone(), sender);
+                Some(watcher)
+            }
+            Err(err) => {
+                println!("Could not watch the config for changes: {}", err);
+                None
+            }
+        };
+        let mut watched_profiles = self.monitor_config_path.clone();
+
+        let mut filter = EventFilter::new(self.monitor_name.clone());
+        for message in receiver {
+            match message {
+                DaemonMessage::Event(event, received) => {
+                    if filter.accept(&event, received) {
+                        filter.handled(received);
+                        self.handle_event(&event);
+                    }
+                }
+                DaemonMessage::ConfigChanged(ConfigChange::Config) => {
+                    let response = self.handle_control(ControlRequest::Reload);
+                    if !response.ok {
+                        println!("{}", response.message);
+                    }
+                    filter = EventFilter::new(self.monitor_name.clone());
+                    self.follow_profile_dir(watcher.as_deref(), &mut watched_profiles);
+                }
+                DaemonMessage::ConfigChanged(ConfigChange::Profile(key)) => {
+                    self.check_profile(&key)
+                }
+                DaemonMessage::Control(request, reply) => {
+                    let changes_monitors = matches!(
+                        request,
+                        ControlRequest::Apply { .. } | ControlRequest::Import { .. }
+                    );
+                    let reloads = request == ControlRequest::Reload;
+                    let started = Instant::now();
+                    let response = self.handle_control(request);
+                    if reloads {
+                        filter = EventFilter::new(self.monitor_name.clone());
+                        self.follow_profile_dir(watcher.as_deref(), &mut watched_profiles);
+                    }
+                    if changes_monitors {
+                        // hyprland reports the changes made for the request
+                        filter.changed_monitors(started);
+                    }
+                    let _ = reply.send(response);
+                }
+            }
+        }
+    }
+
+    fn handle_control(&mut self, request: ControlRequest) -> ControlResponse {
+        match request {
+            ControlRequest::Apply { mode } => {
+                let action = match mode.as_str() {
+                    "internal" => HyprDock::internal_monitor,
+                    "external" => HyprDock::external_monitor,
+                    "extend" => HyprDock::extend_monitor,
+                    "mirror" => HyprDock::mirror_monitor,
+                    _ => return ControlResponse::error(format!("Unknown mode {}", mode)),
+                };
+                self.confirmed(action);
+                ControlResponse::ok(format!("Applied {}", mode))
+            }
+            ControlRequest::Import { profile } => {
+                self.confirmed(|dock| dock.import_profile(profile.as_ref()));
+                ControlResponse::ok("Imported profile")
+            }
+            ControlRequest::Status => {
+                let inhibit = Inhibitor::new().and_then(|inhibitor| inhibitor.state());
+                ControlResponse {
+                    status: Some(DaemonStatus {
+                        inhibit,
+                        active_profile: ProfileStore::new(&self.monitor_config_path).active(),
+                    }),
+                    ..ControlResponse::ok(match inhibit {
+                        Some(state) => format!("running, {}", state.describe()),
+                        None => "running".into(),
+                    })
+                }
+            }
+            ControlRequest::Reload => {
+                let path = config_path();
+                let (config, diagnostics) = reload_config(&path);
+                log_diagnostics(&path, &diagnostics);
+                let config = match config {
+                    Ok(config) => config,
+                    Err(err) => return ControlResponse::error(err),
+                };
+                let changes = self.changes(&config);
+                if changes.is_empty() {
+                    return ControlResponse::ok("Config is unchanged");
+                }
+                for change in &changes {
+                    println!("Config changed: {}", change);
+                }
+                *self = config;
+                ControlResponse::ok(format!(
+                    "Reloaded config, event sources apply after a restart\n{}",
+                    changes.join("\n")
+                ))
+            }
+            ControlRequest::Pause => match inhibit(None) {
+                Ok(message) => ControlResponse::ok(message),
+                Err(message) => ControlResponse::error(message),
+            },
+            ControlRequest::Resume => match resume() {
+                Ok(message) => ControlResponse::ok(message),
+                Err(message) => ControlResponse::error(message),
+            },
+        }
+    }
+
+    /// Moves the profile watch along if a reload changed `monitor_config_path`.
+    fn follow_profile_dir(&self, watcher: Option<&ConfigWatcher>, watched: &mut String) {
+        if *watched == self.monitor_config_path {
+            return;
+        }
+        *watched = self.monitor_config_path.clone();
+        let Some(watcher) = watcher else {
             return;
+        };
+        let profiles = ProfileStore::new(&self.monitor_config_path);
+        if let Err(err) = watcher.watch_profiles(profiles.dir()) {
+            println!("Could not watch {}: {}", profiles.dir().display(), err);
         }
-        let mut sock = sock.unwrap();
-        loop {
-            let mut buf = [0; 1024];
-            let n = sock.read(&mut buf).expect("failed to read from socket");
-            let data = std::str::from_utf8(&buf[..n]).unwrap().to_string();
+    }
 
-            self.handle_event(data.as_str());
+    /// Reports whether a profile that changed on disk can still be used.
+    fn check_profile(&self, key: &str) {
+        let store = ProfileStore::new(&self.monitor_config_path);
+        if store.find(key).is_none() {
+            println!("Profile {} was removed", key);
+            return;
+        }
+        match store.load_monitors(key) {
+            Ok(_) => println!("Profile {} changed", key),
+            Err(err) => println!("Profile {} changed: {}", key, err),
         }
     }
 
+    pub fn import_profile(&self, name: Option<&String>) {
+        set_hypr_monitors_from_file(self.monitor_config_path.clone(), name, None);
+        self.wallpaper();
+        self.reload_bar();
+        self.fix_bar();
+    }
+
     pub fn init(&self) {
-        self.execute_command(self.init_command.format(&self.monitor_name));
+        self.execute_long_running(&self.init_command);
     }
 
+    /// Starts the locker and only suspends once it is ready, so the desktop is never
+    /// visible on resume. If the locker fails, the system is not suspended.
     pub fn lock_system(&self) {
-        self.execute_command(self.lock_command.format(&self.monitor_name));
-        self.execute_command(self.suspend_command.format(&self.monitor_name));
+        let lock = self.format_command(&self.lock_command);
+        let base = lock.base.trim();
+        if !base.is_empty() {
+            let mut locker = match Command::new(base)
+                .args(&lock.args)
+                .stdin(Stdio::null())
+                .spawn()
+            {
+                Ok(locker) => locker,
+                Err(err) => {
+                    println!("Could not start locker {}, not suspending: {}", base, err);
+                    return;
+                }
+            };
+            match self.lock_readiness.wait(&mut locker) {
+                LockerState::Ready => (),
+                LockerState::NotReady => println!(
+                    "Locker {} was not ready after {}s, suspending anyway",
+                    base, self.lock_readiness.max_wait
+                ),
+                LockerState::Failed(status) => {
+                    println!("Locker {} failed with {}, not suspending", base, status);
+                    return;
+                }
+            }
+            thread::spawn(move || locker.wait());
+        }
+        self.execute_command(&self.suspend_command);
     }
 
     pub fn utility(&self) {
-        self.execute_command(self.utility_command.format(&self.monitor_name));
+        self.execute_long_running(&self.utility_command);
     }
 
     pub fn extend_monitor(&self) {
         if !self.is_internal_active() {
             self.restart_internal();
         }
-        self.execute_command(self.extend_command.format(&self.monitor_name));
+        self.execute_command(&self.extend_command);
     }
 
     pub fn mirror_monitor(&self) {
         if !self.is_internal_active() {
             self.restart_internal();
         }
-        self.execute_command(self.mirror_command.format(&self.monitor_name));
+        self.execute_command(&self.mirror_command);
     }
 
     pub fn internal_monitor(&self) {
         let needs_restart = !self.is_internal_active();
-        self.execute_command(
-            self.enable_internal_monitor_command
-                .format(&self.monitor_name),
-        );
-        self.execute_command(
-            self.disable_external_monitor_command
-                .format(&self.monitor_name),
-        );
+        self.execute_command(&self.enable_internal_monitor_command);
+        self.execute_command(&self.disable_external_monitor_command);
         if needs_restart {
             self.reload_bar();
             self.wallpaper();
@@ -464,10 +1019,7 @@ impl HyprDock {
     }
 
     pub fn restart_internal(&self) {
-        self.execute_command(
-            self.enable_internal_monitor_command
-                .format(&self.monitor_name),
-        );
+        self.execute_command(&self.enable_internal_monitor_command);
         self.wallpaper();
         self.reload_bar();
         self.fix_bar();
@@ -478,14 +1030,8 @@ impl HyprDock {
             return;
         }
         let needs_restart = !self.is_internal_active();
-        self.execute_command(
-            self.disable_internal_monitor_command
-                .format(&self.monitor_name),
-        );
-        self.execute_command(
-            self.enable_external_monitor_command
-                .format(&self.monitor_name),
-        );
+        self.execute_command(&self.disable_internal_monitor_command);
+        self.execute_command(&self.enable_external_monitor_command);
         if needs_restart {
             self.reload_bar();
             self.wallpaper();
@@ -493,16 +1039,16 @@ impl HyprDock {
     }
 
     pub fn wallpaper(&self) {
-        self.execute_command(self.wallpaper_command.format(&self.monitor_name));
+        self.execute_long_running(&self.wallpaper_command);
     }
 
     pub fn reload_bar(&self) {
-        self.execute_command(self.close_bar_command.format(&self.monitor_name));
-        self.execute_command(self.open_bar_command.format(&self.monitor_name));
+        self.execute_command(&self.close_bar_command);
+        self.execute_long_running(&self.open_bar_command);
     }
 
     pub fn fix_bar(&self) {
-        self.execute_command(self.reload_bar_command.format(&self.monitor_name));
+        self.execute_long_running(&self.reload_bar_command);
     }
 
     pub fn add_monitor(&self) {
@@ -514,8 +1060,7 @@ impl HyprDock {
     }
 
     pub fn is_internal_active(&self) -> bool {
-        let current_monitors = get_all_hypr_monitors();
-        for monitor in current_monitors {
+        for monitor in current_monitors() {
             if monitor.name == self.monitor_name && !monitor.disabled {
                 return true;
             }
@@ -524,8 +1069,7 @@ impl HyprDock {
     }
 
     pub fn has_external_monitor(&self) -> bool {
-        let current_monitors = get_all_hypr_monitors();
-        for monitor in current_monitors {
+        for monitor in current_monitors() {
             if monitor.name != self.monitor_name && !monitor.disabled {
                 return true;
             }
@@ -533,3 +1077,18 @@ impl HyprDock {
         false
     }
 }
+
+#[test]
+fn command_placeholder_values_are_not_expanded() {
+    let command = HyprdockCommand::new("notify-send", &["$HOME", "{profile}"]);
+    let formatted = command
+        .format(|placeholder| match placeholder {
+            Placeholder::Profile => "$HOME".into(),
+            _ => String::new(),
+        })
+        .unwrap();
+    assert_eq!(
+        formatted.args,
+        vec![env::var("HOME").unwrap_or_default(), "$HOME".into()]
+    );
+}
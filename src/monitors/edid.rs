@@ -0,0 +1,199 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{collections::BTreeMap, fs, sync::Mutex};
+
+use crate::{events::drm::connector_edid, monitors::Monitor};
+
+const EDID_HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+const DESCRIPTOR_OFFSETS: [usize; 4] = [54, 72, 90, 108];
+const DESCRIPTOR_SERIAL: u8 = 0xff;
+const DESCRIPTOR_NAME: u8 = 0xfc;
+/// Vendor names for PNP ids, as used by hyprland for the `make` of a monitor.
+const PNP_IDS: &str = "/usr/share/hwdata/pnp.ids";
+
+/// EDIDs reported by drm hotplug events, by connector name.
+static CONNECTED: Mutex<BTreeMap<String, Edid>> = Mutex::new(BTreeMap::new());
+
+/// Identity of a monitor as read from its EDID, formatted like hyprland does it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Edid {
+    pub make: String,
+    pub model: String,
+    pub serial: String,
+}
+
+impl Edid {
+    /// Parses the base block of an EDID blob. Returns `None` for empty or invalid data,
+    /// which is what the kernel exposes for disconnected connectors.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 128 || data[..8] != EDID_HEADER {
+            return None;
+        }
+        let vendor = u16::from_be_bytes([data[8], data[9]]);
+        let pnp_id: String = [10, 5, 0]
+            .iter()
+            .map(|shift| (b'A' - 1 + ((vendor >> shift) & 0x1f) as u8) as char)
+            .collect();
+        let product = u16::from_le_bytes([data[10], data[11]]);
+        let serial_number = u32::from_le_bytes([data[12], data[13], data[14], data[15]]);
+
+        let mut name = None;
+        let mut serial = None;
+        for offset in DESCRIPTOR_OFFSETS {
+            let descriptor = &data[offset..offset + 18];
+            // display descriptors start with a zero pixel clock
+            if descriptor[..3] != [0, 0, 0] {
+                continue;
+            }
+            match descriptor[3] {
+                DESCRIPTOR_NAME => name = descriptor_text(descriptor),
+                DESCRIPTOR_SERIAL => serial = descriptor_text(descriptor),
+                _ => (),
+            }
+        }
+        Some(Self {
+            make: pnp_vendor_name(&pnp_id).unwrap_or(pnp_id),
+            model: name.unwrap_or_else(|| format!("0x{:04X}", product)),
+            serial: serial.unwrap_or_else(|| {
+                if serial_number == 0 {
+                    String::new()
+                } else {
+                    serial_number.to_string()
+                }
+            }),
+        })
+    }
+}
+
+/// Remembers the EDID a hotplug event reported for a connector, `None` forgets it.
+pub fn remember(connector: &str, edid: Option<&Edid>) {
+    let mut connected = CONNECTED.lock().unwrap_or_else(|err| err.into_inner());
+    match edid {
+        Some(edid) => connected.insert(connector.to_string(), edid.clone()),
+        None => connected.remove(connector),
+    };
+}
+
+/// The EDID of a connector, as reported by hotplug events or read from sysfs.
+pub fn lookup(connector: &str) -> Option<Edid> {
+    let connected = CONNECTED.lock().unwrap_or_else(|err| err.into_inner());
+    connected
+        .get(connector)
+        .cloned()
+        .or_else(|| connector_edid(connector))
+}
+
+/// Values hyprland reports when it could not identify a monitor.
+fn is_generic(value: &str) -> bool {
+    let value = value.trim();
+    value.is_empty() || value == "0" || value.eq_ignore_ascii_case("unknown")
+}
+
+/// Replaces empty or generic make, model and serial of monitors with their EDID identity,
+/// so that `[match]` rules still work for monitors hyprland could not identify.
+pub fn fill_identity(monitors: &mut [Monitor], lookup: impl Fn(&str) -> Option<Edid>) {
+    for monitor in monitors.iter_mut() {
+        let fields = [&monitor.make, &monitor.model, &monitor.serial];
+        if !fields.iter().any(|value| is_generic(value)) {
+            continue;
+        }
+        let Some(edid) = lookup(&monitor.name) else {
+            continue;
+        };
+        for (value, edid_value) in [
+            (&mut monitor.make, edid.make),
+            (&mut monitor.model, edid.model),
+            (&mut monitor.serial, edid.serial),
+        ] {
+            if is_generic(value) && !edid_value.is_empty() {
+                *value = edid_value;
+            }
+        }
+    }
+}
+
+fn descriptor_text(descriptor: &[u8]) -> Option<String> {
+    let text = &descriptor[5..18];
+    let end = text.iter().position(|c| *c == b'\n').unwrap_or(text.len());
+    let text = String::from_utf8_lossy(&text[..end]).trim().to_string();
+    if text.is_empty() { None } else { Some(text) }
+}
+
+fn pnp_vendor_name(pnp_id: &str) -> Option<String> {
+    let ids = fs::read_to_string(PNP_IDS).ok()?;
+    ids.lines().find_map(|line| {
+        let (id, name) = line.split_once('\t')?;
+        if id == pnp_id {
+            Some(name.trim().to_string())
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+pub fn example_edid(name: &str, serial: &str) -> Vec<u8> {
+    let mut data = vec![0; 128];
+    data[..8].copy_from_slice(&EDID_HEADER);
+    // "DEL"
+    data[8..10].copy_from_slice(&0x10acu16.to_be_bytes());
+    data[10..12].copy_from_slice(&0xa0c4u16.to_le_bytes());
+    data[12..16].copy_from_slice(&12345u32.to_le_bytes());
+    for (offset, tag, text) in [(72, DESCRIPTOR_SERIAL, serial), (90, DESCRIPTOR_NAME, name)] {
+        data[offset + 3] = tag;
+        let mut padded = format!("{}\n", text).into_bytes();
+        padded.resize(13, b' ');
+        data[offset + 5..offset + 18].copy_from_slice(&padded);
+    }
+    data
+}
+
+#[test]
+fn edid_parse() {
+    let edid = Edid::parse(&example_edid("DELL U2719D", "7KXJ8T2")).unwrap();
+    assert!(edid.make == "DEL" || edid.make == "Dell Inc.");
+    assert_eq!(edid.model, "DELL U2719D");
+    assert_eq!(edid.serial, "7KXJ8T2");
+
+    let mut bare = example_edid("", "");
+    bare[72..108].fill(0);
+    let edid = Edid::parse(&bare).unwrap();
+    assert_eq!(edid.model, "0xA0C4");
+    assert_eq!(edid.serial, "12345");
+
+    assert_eq!(Edid::parse(&[]), None);
+}
+
+#[test]
+fn edid_fill_identity() {
+    use crate::profiles::matching::test_monitor;
+    let mut monitors = vec![
+        test_monitor("DP-1", "Unknown", "", ""),
+        test_monitor("DP-2", "Dell Inc.", "DELL U2719D", "ABC"),
+        test_monitor("HDMI-A-1", "", "", ""),
+    ];
+    let edid = Edid::parse(&example_edid("DELL U2719D", "7KXJ8T2")).unwrap();
+    fill_identity(&mut monitors, |name| {
+        (name != "HDMI-A-1").then(|| edid.clone())
+    });
+    assert_eq!(monitors[0].make, edid.make);
+    assert_eq!(monitors[0].model, "DELL U2719D");
+    assert_eq!(monitors[0].serial, "7KXJ8T2");
+    assert_eq!(monitors[1].serial, "ABC");
+    assert_eq!(monitors[2].model, "");
+}
@@ -0,0 +1,68 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+//! Fingerprints identify a set of connected monitors, independent of the
+//! order in which they are reported and of the connectors they are plugged into.
+//!
+//! Format: `v1-` followed by the lowercase hex SHA-256 of one line per monitor,
+//! `make\tmodel\tserial\n`, sorted bytewise.
+//! The version prefix changes whenever this format does, which allows
+//! migrating saved profiles instead of silently losing them.
+
+use sha2::{Digest, Sha256};
+
+pub const FINGERPRINT_VERSION: &str = "v1";
+
+pub fn fingerprint<'a>(monitors: impl IntoIterator<Item = (&'a str, &'a str, &'a str)>) -> String {
+    let mut lines: Vec<String> = monitors
+        .into_iter()
+        .map(|(make, model, serial)| format!("{}\t{}\t{}\n", make, model, serial))
+        .collect();
+    lines.sort();
+    let mut hasher = Sha256::new();
+    for line in lines {
+        hasher.update(line.as_bytes());
+    }
+    let hex: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}-{}", FINGERPRINT_VERSION, hex)
+}
+
+pub fn is_fingerprint(value: &str) -> bool {
+    value
+        .strip_prefix(FINGERPRINT_VERSION)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[test]
+fn fingerprint_stable() {
+    let laptop = ("Samsung Display Corp.", "0x4174", "");
+    let dell = ("Dell Inc.", "DELL U2719D", "7KXJ8T2");
+    let print = fingerprint([laptop, dell]);
+    assert_eq!(print, fingerprint([dell, laptop]));
+    assert_ne!(print, fingerprint([dell]));
+    assert!(is_fingerprint(&print));
+    // must never change for v1, saved profiles depend on it
+    assert_eq!(
+        fingerprint([("a", "b", "c")]),
+        "v1-bd56df9b747166a38de6b261ee06660e07909915c60eedf81312e4697bd1b8ea"
+    );
+}
@@ -0,0 +1,336 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    env, fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use super::hypr_monitors::HyprMonitor;
+
+/// Separator hyprland places between the replies of a `[[BATCH]]` request.
+const BATCH_REPLY_SEPARATOR: &str = "\n\n\n";
+
+#[derive(Debug)]
+pub enum HyprIpcError {
+    /// An environment variable needed to locate the socket is not set,
+    /// usually because hyprdock is not running inside a hyprland session.
+    MissingEnv(&'static str),
+    Connect(PathBuf, io::Error),
+    Io(io::Error),
+    InvalidUtf8,
+    Json(serde_json::Error),
+    /// Hyprland answered, but did not accept the request.
+    Rejected {
+        request: String,
+        reply: String,
+    },
+}
+
+impl fmt::Display for HyprIpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HyprIpcError::MissingEnv(var) => {
+                write!(f, "{} is not set, is hyprland running?", var)
+            }
+            HyprIpcError::Connect(path, err) => {
+                write!(
+                    f,
+                    "Could not connect to hyprland socket {}: {}",
+                    path.display(),
+                    err
+                )
+            }
+            HyprIpcError::Io(err) => write!(f, "Hyprland socket error: {}", err),
+            HyprIpcError::InvalidUtf8 => write!(f, "Hyprland replied with invalid utf8"),
+            HyprIpcError::Json(err) => write!(f, "Could not parse hyprland reply: {}", err),
+            HyprIpcError::Rejected { request, reply } => {
+                write!(f, "Hyprland rejected \"{}\": {}", request, reply.trim())
+            }
+        }
+    }
+}
+
+impl std::error::Error for HyprIpcError {}
+
+impl From<io::Error> for HyprIpcError {
+    fn from(err: io::Error) -> Self {
+        HyprIpcError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for HyprIpcError {
+    fn from(err: serde_json::Error) -> Self {
+        HyprIpcError::Json(err)
+    }
+}
+
+/// Returns the directory containing the sockets of the current hyprland instance.
+pub fn hypr_socket_dir() -> Result<PathBuf, HyprIpcError> {
+    let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE")
+        .map_err(|_| HyprIpcError::MissingEnv("HYPRLAND_INSTANCE_SIGNATURE"))?;
+    let runtime_dir =
+        env::var("XDG_RUNTIME_DIR").map_err(|_| HyprIpcError::MissingEnv("XDG_RUNTIME_DIR"))?;
+    Ok(PathBuf::from(runtime_dir).join("hypr").join(signature))
+}
+
+/// Minimal client for hyprland's request socket (`.socket.sock`).
+/// Every request opens a new connection, as hyprland closes it after replying.
+pub struct HyprClient {
+    socket_path: PathBuf,
+}
+
+impl HyprClient {
+    pub fn new() -> Result<Self, HyprIpcError> {
+        Ok(Self::with_path(hypr_socket_dir()?.join(".socket.sock")))
+    }
+
+    pub fn with_path(socket_path: PathBuf) -> Self {
+        Self { socket_path }
+    }
+
+    /// Sends a raw request like `j/monitors all` and returns the full reply.
+    pub fn request(&self, request: &str) -> Result<String, HyprIpcError> {
+        let mut stream = UnixStream::connect(&self.socket_path)
+            .map_err(|err| HyprIpcError::Connect(self.socket_path.clone(), err))?;
+        stream.write_all(request.as_bytes())?;
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply)?;
+        String::from_utf8(reply).map_err(|_| HyprIpcError::InvalidUtf8)
+    }
+
+    /// All monitors known to hyprland, including disabled ones.
+    pub fn all_monitors(&self) -> Result<Vec<HyprMonitor>, HyprIpcError> {
+        Ok(serde_json::from_str(&self.all_monitors_raw()?)?)
+    }
+
+    pub fn all_monitors_raw(&self) -> Result<String, HyprIpcError> {
+        self.request("j/monitors all")
+    }
+
+    /// Only the currently active monitors, as returned by `hyprctl -j monitors`.
+    pub fn active_monitors_raw(&self) -> Result<String, HyprIpcError> {
+        self.request("j/monitors")
+    }
+
+    pub fn keyword(&self, keyword: &str, value: &str) -> Result<(), HyprIpcError> {
+        let request = format!("keyword {} {}", keyword, value);
+        let reply = self.request(&request)?;
+        check_reply(request, reply)
+    }
+
+    /// Sends all requests as a single `[[BATCH]]`, which hyprland applies in one go.
+    /// The result contains one entry per request, in the same order.
+    pub fn batch(
+        &self,
+        requests: &[String],
+    ) -> Result<Vec<Result<(), HyprIpcError>>, HyprIpcError> {
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+        let reply = self.request(&format!("[[BATCH]]{}", requests.join(";")))?;
+        let mut replies = reply.split(BATCH_REPLY_SEPARATOR);
+        Ok(requests
+            .iter()
+            .map(|request| {
+                check_reply(
+                    request.clone(),
+                    replies.next().unwrap_or_default().to_string(),
+                )
+            })
+            .collect())
+    }
+}
+
+fn check_reply(request: String, reply: String) -> Result<(), HyprIpcError> {
+    if reply.trim() == "ok" {
+        Ok(())
+    } else {
+        Err(HyprIpcError::Rejected { request, reply })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HyprEvent {
+    MonitorAdded { name: String, description: String },
+    MonitorRemoved { name: String },
+}
+
+/// Parses a single line of hyprland's event socket, e.g. `monitorremoved>>DP-1`.
+/// Events hyprdock does not care about are returned as `None`.
+pub fn parse_hypr_event(line: &str) -> Option<HyprEvent> {
+    let (event, data) = line.trim_end().split_once(">>")?;
+    match event {
+        // monitoraddedv2>>ID,NAME,DESCRIPTION
+        "monitoraddedv2" => {
+            let mut fields = data.splitn(3, ',');
+            fields.next()?;
+            Some(HyprEvent::MonitorAdded {
+                name: fields.next()?.to_string(),
+                description: fields.next().unwrap_or_default().to_string(),
+            })
+        }
+        "monitorremoved" => Some(HyprEvent::MonitorRemoved {
+            name: data.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+/// Reader for hyprland's event socket (`.socket2.sock`).
+pub struct HyprEventListener {
+    reader: BufReader<UnixStream>,
+}
+
+impl HyprEventListener {
+    pub fn new() -> Result<Self, HyprIpcError> {
+        Self::with_path(hypr_socket_dir()?.join(".socket2.sock"))
+    }
+
+    pub fn with_path(socket_path: PathBuf) -> Result<Self, HyprIpcError> {
+        let stream = UnixStream::connect(&socket_path)
+            .map_err(|err| HyprIpcError::Connect(socket_path, err))?;
+        Ok(Self {
+            reader: BufReader::new(stream),
+        })
+    }
+
+    /// Blocks until the next relevant event arrives.
+    /// Returns `None` once hyprland closes the socket.
+    pub fn next_event(&mut self) -> Result<Option<HyprEvent>, HyprIpcError> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if let Some(event) = parse_hypr_event(&line) {
+                return Ok(Some(event));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub fn fake_hypr_server(
+    name: &str,
+    replies: Vec<impl AsRef<str> + Send + 'static>,
+) -> (PathBuf, std::thread::JoinHandle<Vec<String>>) {
+    use std::os::unix::net::UnixListener;
+    let path = env::temp_dir().join(format!("hyprdock-{}-{}.sock", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).expect("Could not bind fake hyprland socket");
+    let handle = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for reply in replies {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 8192];
+            let n = stream.read(&mut buf).unwrap();
+            requests.push(String::from_utf8_lossy(&buf[..n]).to_string());
+            stream.write_all(reply.as_ref().as_bytes()).unwrap();
+        }
+        requests
+    });
+    (path, handle)
+}
+
+#[test]
+fn ipc_monitors_and_keyword() {
+    let (path, server) = fake_hypr_server(
+        "ipc-keyword",
+        vec![include_str!("../../example.json"), "ok", "err"],
+    );
+    let client = HyprClient::with_path(path.clone());
+
+    let monitors = client.all_monitors().expect("Could not parse monitors");
+    assert_eq!(monitors.len(), 1);
+    assert!(client.keyword("monitor", "eDP-1,disabled").is_ok());
+    assert!(matches!(
+        client.keyword("monitor", "bogus"),
+        Err(HyprIpcError::Rejected { .. })
+    ));
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests[0], "j/monitors all");
+    assert_eq!(requests[1], "keyword monitor eDP-1,disabled");
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn ipc_batch() {
+    let (path, server) = fake_hypr_server("ipc-batch", vec!["ok\n\n\nbad rule\n\n\n"]);
+    let client = HyprClient::with_path(path.clone());
+
+    let results = client
+        .batch(&[
+            "keyword monitor eDP-1,disabled".into(),
+            "keyword monitor DP-1,nonsense".into(),
+        ])
+        .expect("Batch request failed");
+    assert!(results[0].is_ok());
+    assert!(results[1].is_err());
+
+    let requests = server.join().unwrap();
+    assert_eq!(
+        requests[0],
+        "[[BATCH]]keyword monitor eDP-1,disabled;keyword monitor DP-1,nonsense"
+    );
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn ipc_connect_error() {
+    let client = HyprClient::with_path(PathBuf::from("/nonexistent/hyprdock.sock"));
+    assert!(matches!(
+        client.request("j/monitors"),
+        Err(HyprIpcError::Connect(..))
+    ));
+}
+
+#[test]
+fn ipc_event_socket() {
+    use std::os::unix::net::UnixListener;
+    let path = env::temp_dir().join(format!("hyprdock-ipc-events-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let server = UnixListener::bind(&path).expect("Could not bind fake hyprland socket");
+    let mut listener = HyprEventListener::with_path(path.clone()).unwrap();
+    let (mut stream, _) = server.accept().unwrap();
+    stream
+        .write_all(
+            b"workspace>>2\nmonitoraddedv2>>1,DP-1,Dell Inc. DELL U2719D\nmonitorremoved>>DP-1\n",
+        )
+        .unwrap();
+    drop(stream);
+
+    assert_eq!(
+        listener.next_event().unwrap(),
+        Some(HyprEvent::MonitorAdded {
+            name: "DP-1".into(),
+            description: "Dell Inc. DELL U2719D".into()
+        })
+    );
+    assert_eq!(
+        listener.next_event().unwrap(),
+        Some(HyprEvent::MonitorRemoved {
+            name: "DP-1".into()
+        })
+    );
+    assert_eq!(listener.next_event().unwrap(), None);
+    let _ = std::fs::remove_file(path);
+}
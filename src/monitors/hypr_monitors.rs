@@ -17,16 +17,26 @@ along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 use std::{
     collections::hash_map::DefaultHasher,
-    fs::File,
+    fs,
     hash::{Hash, Hasher},
-    io::Write,
     path::PathBuf,
-    process::Command,
 };
 
 use serde::{Deserialize, Serialize};
 
-use super::Monitor;
+use crate::profiles::{
+    ProfileError, ProfileFormat, ProfileStore,
+    hooks::run_hooks,
+    toml_profile::{OutputConfig, TomlProfile},
+};
+
+use super::{
+    Monitor, apply_hypr_monitors, edid,
+    fingerprint::fingerprint,
+    hypr_ipc::{HyprClient, HyprIpcError},
+    model::{Mode, Position, Scale, Transform},
+    report_apply_errors,
+};
 
 #[allow(non_snake_case)]
 #[derive(Serialize, Deserialize, Debug)]
@@ -46,8 +56,18 @@ pub struct HyprMonitor {
     transform: i64,
     vrr: bool,
     disabled: bool,
+    /// Name of the mirrored monitor, or `none`.
+    #[serde(default)]
+    mirrorOf: String,
+    /// DRM pixel format, e.g. `XRGB2101010` for 10 bit.
+    #[serde(default)]
+    currentFormat: String,
+    /// e.g. `1920x1080@60.00Hz`
+    #[serde(default)]
+    availableModes: Vec<String>,
 }
 
+/// Only used to recognize profiles saved before fingerprints were introduced.
 impl Hash for HyprMonitor {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.name.hash(state);
@@ -65,68 +85,218 @@ impl HyprMonitor {
             make: self.make.clone(),
             model: self.model.clone(),
             serial: self.serial.clone(),
-            resolution: self.width.to_string() + "x" + &self.height.to_string(),
-            refreshrate: (self.refreshRate as i64).to_string(),
-            offset: self.x.to_string() + "x" + &self.y.to_string(),
-            scale: self.scale.to_string(),
-            transform: self.transform.to_string(),
+            mode: Mode::from_hz(
+                u32::try_from(self.width).unwrap_or_default(),
+                u32::try_from(self.height).unwrap_or_default(),
+                self.refreshRate,
+            ),
+            position: Position {
+                x: self.x as i32,
+                y: self.y as i32,
+            },
+            scale: Scale::new(self.scale).unwrap_or_default(),
+            transform: Transform::try_from(self.transform).unwrap_or_default(),
+            available_modes: self
+                .availableModes
+                .iter()
+                .filter_map(|mode| mode.parse().ok())
+                .collect(),
             vrr: self.vrr,
             disabled: self.disabled,
+            mirror: self.mirror(),
+            bitdepth: self.bitdepth(),
+        }
+    }
+
+    fn mirror(&self) -> Option<String> {
+        match self.mirrorOf.as_str() {
+            "" | "none" => None,
+            mirror => Some(mirror.to_string()),
+        }
+    }
+
+    fn bitdepth(&self) -> Option<u8> {
+        if self.currentFormat.contains("2101010") {
+            Some(10)
+        } else {
+            None
         }
     }
 }
 
-pub fn get_all_hypr_monitors() -> Vec<Monitor> {
-    let output = Command::new("hyprctl")
-        .args(["-j", "monitors", "all"])
-        .output()
-        .expect("Could not save output to file")
-        .stdout;
-    let monitors: Vec<HyprMonitor> = serde_json::from_str(
-        String::from_utf8(output)
-            .expect("Could not convert output to string")
-            .as_str(),
-    )
-    .expect("Could not parse json");
-    monitors
+pub fn get_all_hypr_monitors() -> Result<Vec<Monitor>, HyprIpcError> {
+    Ok(HyprClient::new()?
+        .all_monitors()?
         .into_iter()
         .map(|this| this.convert_data())
-        .collect::<Vec<_>>()
+        .collect::<Vec<_>>())
+}
+
+pub fn get_hypr_monitor_info() -> Result<String, HyprIpcError> {
+    HyprClient::new()?.active_monitors_raw()
+}
+
+/// Includes disabled monitors, so profiles can restore them as disabled.
+pub fn get_all_hypr_monitor_info() -> Result<String, HyprIpcError> {
+    HyprClient::new()?.all_monitors_raw()
+}
+
+pub fn monitor_fingerprint(monitors: &[HyprMonitor]) -> String {
+    fingerprint(monitors.iter().map(|monitor| {
+        (
+            monitor.make.as_str(),
+            monitor.model.as_str(),
+            monitor.serial.as_str(),
+        )
+    }))
 }
 
-pub fn get_hypr_monitor_info() -> Vec<u8> {
-    Command::new("hyprctl")
-        .args(["-j", "monitors"])
-        .output()
-        .expect("Could not save output to file")
-        .stdout
+pub fn get_current_monitor_hash() -> Result<String, HyprIpcError> {
+    let monitors: Vec<HyprMonitor> = serde_json::from_str(&get_hypr_monitor_info()?)?;
+    Ok(monitor_fingerprint(&monitors))
 }
 
-pub fn get_current_monitor_hash(name: Option<&String>) -> String {
-    let monitors: Vec<HyprMonitor> = serde_json::from_str(
-        &String::from_utf8(get_hypr_monitor_info()).expect("Could not parse json"),
-    )
-    .expect("Could not parse json");
+/// Profiles are stored by name if given, otherwise by the fingerprint of the monitors.
+fn profile_key(name: Option<&String>, hash: Option<&String>) -> Result<String, HyprIpcError> {
+    match name.or(hash) {
+        Some(key) => Ok(key.clone()),
+        None => get_current_monitor_hash(),
+    }
+}
+
+/// The `DefaultHasher` based hash used before fingerprints, for unnamed profiles.
+fn legacy_monitor_hash(monitors: &[HyprMonitor]) -> String {
     let mut s = DefaultHasher::new();
     for monitor in monitors.iter() {
         monitor.hash(&mut s);
     }
-    name.hash(&mut s);
+    None::<&String>.hash(&mut s);
     s.finish().to_string()
 }
 
-pub fn save_hypr_monitor_data(path: String, name: Option<&String>, hash: Option<&String>) {
-    let monitor_name = get_current_monitor_hash(name);
-    let monitor_hash = hash.unwrap_or(&monitor_name);
-    let mut file = File::create(path + "monitor_configs/" + &monitor_hash + ".json")
-        .expect("Could not open json file");
-    file.write_all(&get_hypr_monitor_info())
-        .expect("Could not write to file");
+/// Renames profiles saved with the legacy hash to their fingerprint.
+/// Named legacy profiles can not be recognized, as the name is only part of the hash,
+/// these are left untouched and reported.
+/// Only runs once per profile directory, so leftovers are not reported on every call.
+pub fn migrate_monitor_configs(base_path: &str) {
+    let store = ProfileStore::new(base_path);
+    if store.migrated() {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(store.dir()) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_legacy = path.extension().is_some_and(|ext| ext == "json")
+            && path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|stem| !stem.is_empty() && stem.chars().all(|c| c.is_ascii_digit()));
+        if !is_legacy {
+            continue;
+        }
+        let monitors: Option<Vec<HyprMonitor>> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok());
+        let Some(monitors) = monitors else {
+            eprintln!("Could not migrate {}: invalid profile", path.display());
+            continue;
+        };
+        if path.file_stem() != Some(legacy_monitor_hash(&monitors).as_ref()) {
+            eprintln!(
+                "Could not migrate {}: named or outdated profile, please export it again",
+                path.display()
+            );
+            continue;
+        }
+        let Ok(new_path) = store.path_for(&monitor_fingerprint(&monitors), ProfileFormat::Json)
+        else {
+            continue;
+        };
+        if new_path.exists() {
+            continue;
+        }
+        match fs::rename(&path, &new_path) {
+            Ok(()) => eprintln!(
+                "Migrated monitor config {} to {}",
+                path.display(),
+                new_path.display()
+            ),
+            Err(err) => eprintln!("Could not migrate {}: {}", path.display(), err),
+        }
+    }
+    if let Err(err) = store.mark_migrated() {
+        eprintln!("Could not record the profile migration: {}", err);
+    }
+}
+
+pub fn save_hypr_monitor_data(
+    path: String,
+    name: Option<&String>,
+    hash: Option<&String>,
+    format: ProfileFormat,
+) {
+    let data = profile_key(name, hash).and_then(|key| Ok((key, get_all_hypr_monitor_info()?)));
+    let (key, monitor_info) = match data {
+        Ok(data) => data,
+        Err(err) => {
+            println!("Could not save monitor configuration: {}", err);
+            return;
+        }
+    };
+    let contents = match format {
+        ProfileFormat::Json => monitor_info,
+        ProfileFormat::Toml => match hypr_data_to_toml(&monitor_info) {
+            Ok(contents) => contents,
+            Err(err) => {
+                println!("Could not convert monitor configuration: {}", err);
+                return;
+            }
+        },
+    };
+    if let Err(err) = ProfileStore::new(path).save(&key, &contents, format) {
+        println!("Could not save monitor configuration: {}", err);
+    }
+}
+
+/// Converts the raw `j/monitors` reply into a [`TomlProfile`].
+fn hypr_data_to_toml(monitor_info: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let monitors: Vec<HyprMonitor> = serde_json::from_str(monitor_info)?;
+    let profile = TomlProfile::new(
+        monitors
+            .iter()
+            .map(|monitor| OutputConfig::from(&monitor.convert_data()))
+            .collect(),
+    );
+    Ok(toml::to_string(&profile)?)
+}
+
+/// Key of the profile for the connected monitors.
+/// A profile saved for their exact fingerprint wins over `[match]` rules of TOML profiles.
+pub fn find_matching_profile(base_path: String) -> Result<Option<String>, HyprIpcError> {
+    let monitors: Vec<HyprMonitor> = serde_json::from_str(&get_hypr_monitor_info()?)?;
+    let store = ProfileStore::new(base_path);
+    let fingerprint = monitor_fingerprint(&monitors);
+    if store.find(&fingerprint).is_some() {
+        return Ok(Some(fingerprint));
+    }
+    let mut monitors: Vec<Monitor> = monitors
+        .iter()
+        .map(|monitor| monitor.convert_data())
+        .collect();
+    edid::fill_identity(&mut monitors, edid::lookup);
+    match store.best_match(&monitors) {
+        Ok(key) => Ok(key),
+        Err(err) => {
+            eprintln!("Could not match profiles: {}", err);
+            Ok(None)
+        }
+    }
 }
 
-pub fn try_get_monitor_hash_path(base_path: String, hash: &String) -> Option<PathBuf> {
-    let path = PathBuf::from(base_path + "/monitor_configs" + &hash + ".json");
-    if path.is_file() { Some(path) } else { None }
+pub fn try_get_monitor_hash_path(base_path: String, hash: &str) -> Option<PathBuf> {
+    ProfileStore::new(base_path).find(hash)
 }
 
 pub fn import_hypr_data(
@@ -134,35 +304,111 @@ pub fn import_hypr_data(
     name: Option<&String>,
     hash: Option<&String>,
 ) -> Option<Vec<Monitor>> {
-    use std::io::prelude::*;
-    let monitor_name = &get_current_monitor_hash(name);
-    let monitor_hash = hash.unwrap_or(monitor_name);
-    let path = try_get_monitor_hash_path(base_path, monitor_hash)?;
-    let mut file = File::open(path).ok()?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).ok()?;
-    let hyprmonitors: Vec<HyprMonitor> = serde_json::from_str(contents.as_str()).ok()?;
-    let mut monitors = Vec::new();
-    for monitor in hyprmonitors {
-        monitors.push(monitor.convert_data());
+    let key = profile_key(name, hash).ok()?;
+    load_profile(&ProfileStore::new(base_path), &key, name.is_some())
+}
+
+/// Missing profiles are only reported if they were explicitly requested by name.
+fn load_profile(store: &ProfileStore, key: &str, report_missing: bool) -> Option<Vec<Monitor>> {
+    match store.load_monitors(key) {
+        Ok(monitors) => Some(monitors),
+        Err(ProfileError::NotFound(_)) if !report_missing => None,
+        Err(err) => {
+            println!("{}", err);
+            None
+        }
     }
-    Some(monitors)
 }
 
 pub fn set_hypr_monitors_from_hyprvec(monitors: Vec<HyprMonitor>) {
-    for monitor in monitors {
-        let new_monitor = monitor.convert_data();
-        new_monitor.enable_hypr_monitor();
+    let monitors: Vec<Monitor> = monitors
+        .iter()
+        .map(|monitor| monitor.convert_data())
+        .collect();
+    match apply_hypr_monitors(&monitors) {
+        Ok(report) => {
+            report_apply_errors(report);
+        }
+        Err(err) => println!("Could not apply monitors: {}", err),
     }
 }
 
 pub fn set_hypr_monitors_from_file(path: String, name: Option<&String>, hash: Option<&String>) {
-    let monitors_opt = import_hypr_data(path, name, hash);
-    if let Some(monitors) = monitors_opt {
-        for monitor in monitors {
-            monitor.enable_hypr_monitor();
+    let Ok(key) = profile_key(name, hash) else {
+        return;
+    };
+    let store = ProfileStore::new(path);
+    let monitors_opt = load_profile(&store, &key, name.is_some());
+    if let Some(mut monitors) = monitors_opt {
+        if store.active().is_some_and(|active| active != key) {
+            leave_profile(&store);
+        }
+        let mut connected = get_all_hypr_monitors().unwrap_or_default();
+        edid::fill_identity(&mut connected, edid::lookup);
+        match store.load_rules(&key) {
+            Ok(Some(rules)) => rules.remap_outputs(&mut monitors, &connected),
+            Ok(None) => (),
+            Err(err) => println!("Could not load match rules of profile {}: {}", key, err),
+        }
+        match apply_hypr_monitors(&supported_monitors(&monitors, &connected)) {
+            Ok(report) => {
+                if !report_apply_errors(report) {
+                    println!("Profile {} was only partially applied", key);
+                }
+            }
+            Err(err) => {
+                println!("Could not apply profile {}: {}", key, err);
+                return;
+            }
+        }
+        if let Err(err) = store.mark_used(&key) {
+            println!("Could not update profile index: {}", err);
+        }
+        match store.load_hooks(&key) {
+            Ok(hooks) => run_hooks(hooks.on_apply, key, monitors),
+            Err(err) => println!("Could not load hooks of profile {}: {}", key, err),
+        }
+    }
+}
+
+/// Checks the monitors of a profile against the modes the connected monitors support.
+/// Monitors with an unsupported mode are reported and left out.
+fn supported_monitors(monitors: &[Monitor], connected: &[Monitor]) -> Vec<Monitor> {
+    let mut supported = Vec::new();
+    for monitor in monitors {
+        let mut monitor = monitor.clone();
+        if let Some(current) = connected
+            .iter()
+            .find(|current| current.name == monitor.name)
+        {
+            monitor.available_modes = current.available_modes.clone();
+        }
+        match monitor.validate() {
+            Ok(()) => supported.push(monitor),
+            Err(err) => println!("Skipping monitor {}: {}", monitor.name, err),
         }
     }
+    supported
+}
+
+/// Runs the `on_leave` hooks of the active profile, for when no profile replaces it.
+pub fn leave_active_profile(path: String) {
+    leave_profile(&ProfileStore::new(path));
+}
+
+fn leave_profile(store: &ProfileStore) {
+    let Some(active) = store.active() else {
+        return;
+    };
+    match (store.load_hooks(&active), store.load_monitors(&active)) {
+        (Ok(hooks), Ok(monitors)) => run_hooks(hooks.on_leave, active, monitors),
+        (Err(err), _) | (_, Err(err)) => {
+            println!("Could not load hooks of profile {}: {}", active, err)
+        }
+    }
+    if let Err(err) = store.clear_active() {
+        println!("Could not update profile index: {}", err);
+    }
 }
 
 #[test]
@@ -182,3 +428,64 @@ fn import_data_test() {
         serde_json::from_str(contents.as_str()).expect("Could not parse json");
     dbg!(p);
 }
+
+#[test]
+fn migrate_legacy_config() {
+    let base = std::env::temp_dir().join(format!("hyprdock-migrate-{}", std::process::id()));
+    let config_dir = base.join("monitor_configs");
+    let _ = fs::remove_dir_all(&base);
+    fs::create_dir_all(&config_dir).unwrap();
+
+    let contents = include_str!("../../example.json");
+    let monitors: Vec<HyprMonitor> = serde_json::from_str(contents).unwrap();
+    let legacy = config_dir.join(legacy_monitor_hash(&monitors) + ".json");
+    fs::write(&legacy, contents).unwrap();
+    let named = config_dir.join("1234.json");
+    fs::write(&named, contents).unwrap();
+
+    migrate_monitor_configs(base.to_str().unwrap());
+    assert!(!legacy.exists());
+    assert!(named.exists());
+    assert!(
+        config_dir
+            .join(monitor_fingerprint(&monitors) + ".json")
+            .is_file()
+    );
+
+    // only migrated once
+    fs::write(&legacy, contents).unwrap();
+    migrate_monitor_configs(base.to_str().unwrap());
+    assert!(legacy.exists());
+    let _ = fs::remove_dir_all(base);
+}
+
+#[test]
+fn export_toml_profile() {
+    let contents = hypr_data_to_toml(include_str!("../../example.json")).unwrap();
+    let profile = TomlProfile::parse(&contents).unwrap();
+    let json: Vec<HyprMonitor> = serde_json::from_str(include_str!("../../example.json")).unwrap();
+    assert_eq!(profile.outputs.len(), json.len());
+    assert_eq!(profile.outputs[0].name, json[0].name);
+    assert_eq!(
+        profile.outputs[0].mode,
+        format!(
+            "{}x{}@{}",
+            json[0].width, json[0].height, json[0].refreshRate
+        )
+    );
+}
+
+#[test]
+fn skip_unsupported_modes() {
+    let connected = ProfileFormat::Json
+        .parse_monitors(include_str!("../../example.json"))
+        .unwrap();
+    let mut profile = connected.clone();
+    for monitor in profile.iter_mut() {
+        monitor.available_modes.clear();
+    }
+    assert_eq!(supported_monitors(&profile, &connected).len(), 1);
+    profile[0].set_resolution(1920, 1080);
+    assert!(supported_monitors(&profile, &connected).is_empty());
+    assert_eq!(supported_monitors(&profile, &[]).len(), 1);
+}
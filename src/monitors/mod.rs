@@ -15,62 +15,150 @@ You should have received a copy of the GNU Affero General Public License
 along with this program. If not, see <http://www.gnu.org/licenses/>.
 */
 
-use std::process::Command;
+use hypr_ipc::{HyprClient, HyprIpcError};
+use model::{Mode, ModelError, Position, Scale, Transform};
 
+#[cfg(test)]
+use crate::profiles::ProfileFormat;
+
+pub mod edid;
+pub mod fingerprint;
+pub mod hypr_ipc;
 pub mod hypr_monitors;
+pub mod model;
+pub mod snapshot;
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Monitor {
     pub name: String,
     pub make: String,
     pub model: String,
     pub serial: String,
-    pub resolution: String,
-    pub refreshrate: String,
-    pub offset: String,
-    pub scale: String,
-    pub transform: String,
+    pub mode: Mode,
+    pub position: Position,
+    pub scale: Scale,
+    pub transform: Transform,
+    /// Modes reported by hyprland, empty if unknown, e.g. for profiles.
+    pub available_modes: Vec<Mode>,
     pub vrr: bool,
     pub disabled: bool,
+    /// Name of the monitor this one mirrors.
+    pub mirror: Option<String>,
+    /// Only 10 bit needs to be requested explicitly, 8 bit is the default.
+    pub bitdepth: Option<u8>,
 }
 
 impl Monitor {
-    pub fn set_resolution(&mut self, new_resolution: String) {
-        self.resolution = new_resolution;
+    pub fn set_mode(&mut self, new_mode: Mode) {
+        self.mode = new_mode;
+    }
+    pub fn set_resolution(&mut self, width: u32, height: u32) {
+        self.mode.width = width;
+        self.mode.height = height;
     }
-    pub fn set_refreshrate(&mut self, new_refreshrate: String) {
-        self.refreshrate = new_refreshrate;
+    pub fn set_refreshrate(&mut self, refresh_mhz: u32) {
+        self.mode.refresh_mhz = refresh_mhz;
     }
-    pub fn set_offset(&mut self, new_offset: String) {
-        self.offset = new_offset;
+    pub fn set_offset(&mut self, new_position: Position) {
+        self.position = new_position;
     }
-    pub fn set_scale(&mut self, new_scale: String) {
+    pub fn set_scale(&mut self, new_scale: Scale) {
         self.scale = new_scale;
     }
-    pub fn set_transform(&mut self, new_transform: String) {
+    pub fn set_transform(&mut self, new_transform: Transform) {
         self.transform = new_transform;
     }
     pub fn set_vrr(&mut self, new_vrr: bool) {
         self.vrr = new_vrr;
     }
+    pub fn set_mirror(&mut self, new_mirror: Option<String>) {
+        self.mirror = new_mirror;
+    }
+    pub fn set_bitdepth(&mut self, new_bitdepth: Option<u8>) {
+        self.bitdepth = new_bitdepth;
+    }
+
+    /// Checks the mode against the modes the monitor supports, if they are known.
+    pub fn validate(&self) -> Result<(), ModelError> {
+        if self.disabled
+            || self.available_modes.is_empty()
+            || self
+                .available_modes
+                .iter()
+                .any(|mode| mode.matches(&self.mode))
+        {
+            Ok(())
+        } else {
+            Err(ModelError::UnsupportedMode {
+                monitor: self.name.clone(),
+                mode: self.mode,
+            })
+        }
+    }
 }
 
 /// Hyprland implementation
 impl Monitor {
-    pub fn enable_hypr_monitor(&self) {
-        let monitor_string = format!(
-            "{},{}@{},{},{},transform,{}",
-            self.name, self.resolution, self.refreshrate, self.offset, self.scale, self.transform
+    /// The value of a `monitor` keyword for this monitor.
+    pub fn hypr_rule(&self) -> String {
+        if self.disabled {
+            return format!("{},disabled", self.name);
+        }
+        let mut rule = format!(
+            "{},{},{},{},transform,{},vrr,{}",
+            self.name, self.mode, self.position, self.scale, self.transform, self.vrr as u8
         );
-        Command::new("hyprctl")
-            .args(["keyword", "monitor", &monitor_string])
-            .spawn()
-            .expect("Could not enable specified monitor");
+        if let Some(mirror) = &self.mirror {
+            rule += &format!(",mirror,{}", mirror);
+        }
+        if let Some(bitdepth) = self.bitdepth {
+            rule += &format!(",bitdepth,{}", bitdepth);
+        }
+        rule
     }
 }
 
+/// Outcome of applying a monitor profile, one entry per monitor.
+pub type ApplyReport = Vec<(String, Result<(), HyprIpcError>)>;
+
+/// Applies all monitors in a single batch request and waits for hyprland to reply.
+/// Monitors are disabled before any are enabled, so the old and new layout never overlap.
+pub fn apply_hypr_monitors(monitors: &[Monitor]) -> Result<ApplyReport, HyprIpcError> {
+    apply_hypr_monitors_with(&HyprClient::new()?, monitors)
+}
+
+pub fn apply_hypr_monitors_with(
+    client: &HyprClient,
+    monitors: &[Monitor],
+) -> Result<ApplyReport, HyprIpcError> {
+    let mut ordered: Vec<&Monitor> = monitors.iter().collect();
+    ordered.sort_by_key(|monitor| !monitor.disabled);
+    let requests: Vec<String> = ordered
+        .iter()
+        .map(|monitor| format!("keyword monitor {}", monitor.hypr_rule()))
+        .collect();
+    Ok(ordered
+        .iter()
+        .map(|monitor| monitor.name.clone())
+        .zip(client.batch(&requests)?)
+        .collect())
+}
+
+/// Prints every monitor that could not be applied, returns whether all succeeded.
+pub fn report_apply_errors(report: ApplyReport) -> bool {
+    let mut success = true;
+    for (name, result) in report {
+        if let Err(err) = result {
+            println!("Could not apply monitor {}: {}", name, err);
+            success = false;
+        }
+    }
+    success
+}
+
 #[test]
 fn monitor_import() {
-    use std::{fs::File, io::Write};
+    use std::{fs::File, io::Write, process::Command};
     let output = Command::new("hyprctl")
         .args(["-j", "monitors", "all"])
         .output();
@@ -83,3 +171,76 @@ fn monitor_import() {
     let mut file = File::create("example.json").expect("Could not open json file");
     assert!(file.write_all(&output).is_ok());
 }
+
+#[test]
+fn apply_batch_order() {
+    use hypr_ipc::fake_hypr_server;
+    let (path, server) = fake_hypr_server("apply-batch", vec!["ok\n\n\nok\n\n\ninvalid scale"]);
+    let monitor = |name: &str| {
+        let mut monitor = ProfileFormat::Json
+            .parse_monitors(include_str!("../../example.json"))
+            .unwrap()
+            .remove(0);
+        monitor.name = name.into();
+        monitor
+    };
+    let mut internal = monitor("eDP-1");
+    internal.disabled = true;
+    let mut external = monitor("DP-1");
+    external.scale = Scale::new(7.0).unwrap();
+
+    let report = apply_hypr_monitors_with(
+        &HyprClient::with_path(path.clone()),
+        &[monitor("HDMI-A-1"), internal, external],
+    )
+    .unwrap();
+    let names: Vec<&str> = report.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec!["eDP-1", "HDMI-A-1", "DP-1"]);
+    assert!(report[0].1.is_ok());
+    assert!(report[2].1.is_err());
+
+    let requests = server.join().unwrap();
+    assert!(
+        requests[0]
+            .starts_with("[[BATCH]]keyword monitor eDP-1,disabled;keyword monitor HDMI-A-1,")
+    );
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn hypr_rule_options() {
+    let mut monitor = ProfileFormat::Json
+        .parse_monitors(include_str!("../../example.json"))
+        .unwrap()
+        .remove(0);
+    assert_eq!(
+        monitor.hypr_rule(),
+        "eDP-1,3840x2400@60,0x0,2,transform,0,vrr,0"
+    );
+    monitor.set_vrr(true);
+    monitor.set_mirror(Some("DP-1".into()));
+    monitor.set_bitdepth(Some(10));
+    assert_eq!(
+        monitor.hypr_rule(),
+        "eDP-1,3840x2400@60,0x0,2,transform,0,vrr,1,mirror,DP-1,bitdepth,10"
+    );
+    monitor.disabled = true;
+    assert_eq!(monitor.hypr_rule(), "eDP-1,disabled");
+}
+
+#[test]
+fn validate_available_modes() {
+    let mut monitor = ProfileFormat::Json
+        .parse_monitors(include_str!("../../example.json"))
+        .unwrap()
+        .remove(0);
+    assert_eq!(monitor.available_modes.len(), 1);
+    assert!(monitor.validate().is_ok());
+    monitor.set_refreshrate(59_940);
+    assert!(matches!(
+        monitor.validate(),
+        Err(ModelError::UnsupportedMode { .. })
+    ));
+    monitor.available_modes.clear();
+    assert!(monitor.validate().is_ok());
+}
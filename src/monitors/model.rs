@@ -0,0 +1,263 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{fmt, str::FromStr};
+
+/// Refresh rates of the same mode can differ slightly between hyprland's
+/// `refreshRate` and `availableModes`, which are rounded to two decimals.
+const REFRESH_TOLERANCE_MHZ: u32 = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelError {
+    InvalidMode(String),
+    InvalidPosition(String),
+    InvalidScale(f64),
+    InvalidTransform(i64),
+    UnsupportedMode { monitor: String, mode: Mode },
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::InvalidMode(mode) => {
+                write!(f, "Invalid mode {}, expected <width>x<height>@<rate>", mode)
+            }
+            ModelError::InvalidPosition(position) => {
+                write!(f, "Invalid position {}, expected <x>x<y>", position)
+            }
+            ModelError::InvalidScale(scale) => write!(f, "Invalid scale {}", scale),
+            ModelError::InvalidTransform(transform) => {
+                write!(f, "Invalid transform {}, expected 0 to 7", transform)
+            }
+            ModelError::UnsupportedMode { monitor, mode } => {
+                write!(f, "Monitor {} does not support mode {}", monitor, mode)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+/// Resolution and refresh rate, formatted as `1920x1080@59.94`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mode {
+    pub width: u32,
+    pub height: u32,
+    /// Refresh rate in millihertz, so fractional rates like 59.94Hz survive.
+    pub refresh_mhz: u32,
+}
+
+impl Mode {
+    pub fn from_hz(width: u32, height: u32, refresh_hz: f64) -> Self {
+        Self {
+            width,
+            height,
+            refresh_mhz: (refresh_hz * 1000.0).round() as u32,
+        }
+    }
+
+    /// Whether both describe the same mode, ignoring rounding of the refresh rate.
+    pub fn matches(&self, other: &Mode) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self.refresh_mhz.abs_diff(other.refresh_mhz) <= REFRESH_TOLERANCE_MHZ
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}@{}",
+            self.width,
+            self.height,
+            self.refresh_mhz / 1000
+        )?;
+        let fraction = self.refresh_mhz % 1000;
+        if fraction != 0 {
+            let digits = format!("{:03}", fraction);
+            write!(f, ".{}", digits.trim_end_matches('0'))?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `1920x1080@60`, `1920x1080@59.94Hz` as listed in `availableModes`,
+/// or `1920x1080`, which defaults to 60Hz.
+impl FromStr for Mode {
+    type Err = ModelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ModelError::InvalidMode(s.to_string());
+        let (resolution, refresh) = s.split_once('@').unwrap_or((s, "60"));
+        let (width, height) = resolution.split_once('x').ok_or_else(invalid)?;
+        let refresh_hz: f64 = refresh
+            .trim_end_matches("Hz")
+            .parse()
+            .map_err(|_| invalid())?;
+        let mode = Mode::from_hz(
+            width.parse().map_err(|_| invalid())?,
+            height.parse().map_err(|_| invalid())?,
+            refresh_hz,
+        );
+        if mode.width == 0 || mode.height == 0 || !refresh_hz.is_finite() || mode.refresh_mhz == 0 {
+            return Err(invalid());
+        }
+        Ok(mode)
+    }
+}
+
+/// Position of the top left corner in the layout, formatted as `1920x0`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.x, self.y)
+    }
+}
+
+impl FromStr for Position {
+    type Err = ModelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ModelError::InvalidPosition(s.to_string());
+        let (x, y) = s.split_once('x').ok_or_else(invalid)?;
+        Ok(Position {
+            x: x.parse().map_err(|_| invalid())?,
+            y: y.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale(f64);
+
+impl Scale {
+    pub fn new(scale: f64) -> Result<Self, ModelError> {
+        if scale.is_finite() && scale > 0.0 {
+            Ok(Scale(scale))
+        } else {
+            Err(ModelError::InvalidScale(scale))
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Scale(1.0)
+    }
+}
+
+impl fmt::Display for Scale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Hyprland's `wl_output_transform` values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transform {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+
+impl Transform {
+    const ALL: [Transform; 8] = [
+        Transform::Normal,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::Flipped,
+        Transform::Flipped90,
+        Transform::Flipped180,
+        Transform::Flipped270,
+    ];
+
+    pub fn value(self) -> i64 {
+        self as i64
+    }
+}
+
+impl TryFrom<i64> for Transform {
+    type Error = ModelError;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        usize::try_from(value)
+            .ok()
+            .and_then(|index| Transform::ALL.get(index).copied())
+            .ok_or(ModelError::InvalidTransform(value))
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value())
+    }
+}
+
+#[test]
+fn mode_parse_and_format() {
+    let mode: Mode = "2560x1440@59.951".parse().unwrap();
+    assert_eq!(mode.refresh_mhz, 59951);
+    assert_eq!(mode.to_string(), "2560x1440@59.951");
+    assert_eq!(
+        Mode::from_hz(1920, 1080, 143.98).to_string(),
+        "1920x1080@143.98"
+    );
+    assert_eq!(
+        "1920x1080".parse::<Mode>().unwrap().to_string(),
+        "1920x1080@60"
+    );
+    assert!(
+        "1920x1080@59.95Hz"
+            .parse::<Mode>()
+            .unwrap()
+            .matches(&Mode::from_hz(1920, 1080, 59.951))
+    );
+    for invalid in ["", "1920", "0x1080@60", "1920x1080@abc", "1920x1080@0"] {
+        assert!(invalid.parse::<Mode>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn position_scale_transform() {
+    assert_eq!(
+        "-1920x0".parse::<Position>().unwrap(),
+        Position { x: -1920, y: 0 }
+    );
+    assert!("1920".parse::<Position>().is_err());
+    assert_eq!(Scale::new(1.25).unwrap().to_string(), "1.25");
+    assert!(Scale::new(0.0).is_err());
+    assert_eq!(Transform::try_from(5).unwrap(), Transform::Flipped90);
+    assert_eq!(Transform::Rotate270.to_string(), "3");
+    assert!(Transform::try_from(8).is_err());
+}
@@ -0,0 +1,182 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{thread, time::Duration};
+
+use super::{
+    ApplyReport, Monitor, apply_hypr_monitors_with,
+    hypr_ipc::{HyprClient, HyprIpcError},
+};
+
+/// Commands are awaited, this is only the time hyprland gets to apply a change before it is compared.
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// The monitor layout at one point in time, including disabled monitors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub monitors: Vec<Monitor>,
+}
+
+impl Snapshot {
+    pub fn take(client: &HyprClient) -> Result<Self, HyprIpcError> {
+        Ok(Self {
+            monitors: client
+                .all_monitors()?
+                .iter()
+                .map(|monitor| monitor.convert_data())
+                .collect(),
+        })
+    }
+
+    pub fn restore(&self, client: &HyprClient) -> Result<ApplyReport, HyprIpcError> {
+        apply_hypr_monitors_with(client, &self.monitors)
+    }
+
+    /// Why this snapshot can not be restored on top of the current layout, if at all.
+    /// Restoring it would leave every monitor disabled, or configure connectors
+    /// that were unplugged in the meantime.
+    pub fn unrestorable(&self, current: &Snapshot) -> Option<String> {
+        if self.monitors.iter().all(|monitor| monitor.disabled) {
+            return Some("it has no enabled monitor".into());
+        }
+        let missing: Vec<&str> = self
+            .monitors
+            .iter()
+            .filter(|monitor| {
+                !current
+                    .monitors
+                    .iter()
+                    .any(|connected| connected.name == monitor.name)
+            })
+            .map(|monitor| monitor.name.as_str())
+            .collect();
+        if missing.is_empty() {
+            None
+        } else {
+            Some(format!("{} is no longer connected", missing.join(", ")))
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfirmOutcome {
+    /// The action did not change the layout, so there was nothing to confirm.
+    Unchanged,
+    Confirmed,
+    Reverted,
+    /// Not confirmed, but the previous layout could not be restored for the given reason.
+    Kept(String),
+}
+
+/// Runs the action and asks for confirmation if it changed the monitor layout.
+/// Without confirmation the layout from before the action is restored.
+pub fn confirm_or_revert(
+    client: &HyprClient,
+    action: impl FnOnce(),
+    confirm: impl FnOnce() -> bool,
+) -> Result<ConfirmOutcome, HyprIpcError> {
+    confirm_or_revert_after(client, action, confirm, SETTLE_TIME)
+}
+
+fn confirm_or_revert_after(
+    client: &HyprClient,
+    action: impl FnOnce(),
+    confirm: impl FnOnce() -> bool,
+    settle_time: Duration,
+) -> Result<ConfirmOutcome, HyprIpcError> {
+    let before = Snapshot::take(client)?;
+    action();
+    thread::sleep(settle_time);
+    if Snapshot::take(client)? == before {
+        return Ok(ConfirmOutcome::Unchanged);
+    }
+    if confirm() {
+        return Ok(ConfirmOutcome::Confirmed);
+    }
+    // monitors may have been unplugged while waiting for the confirmation
+    if let Some(reason) = before.unrestorable(&Snapshot::take(client)?) {
+        return Ok(ConfirmOutcome::Kept(reason));
+    }
+    for (name, result) in before.restore(client)? {
+        if let Err(err) = result {
+            println!("Could not revert monitor {}: {}", name, err);
+        }
+    }
+    Ok(ConfirmOutcome::Reverted)
+}
+
+#[test]
+fn revert_without_confirmation() {
+    use super::hypr_ipc::fake_hypr_server;
+    let before = include_str!("../../example.json");
+    let after = before.replace("\"scale\": 2.00", "\"scale\": 1.00");
+    let (path, server) = fake_hypr_server(
+        "confirm-revert",
+        vec![before.to_string(), after.clone(), after, "ok".to_string()],
+    );
+    let client = HyprClient::with_path(path.clone());
+
+    let outcome =
+        confirm_or_revert_after(&client, || {}, || false, Duration::ZERO).expect("Revert failed");
+    assert_eq!(outcome, ConfirmOutcome::Reverted);
+    let requests = server.join().unwrap();
+    assert_eq!(
+        requests[3],
+        "[[BATCH]]keyword monitor eDP-1,3840x2400@60,0x0,2,transform,0,vrr,0"
+    );
+    let _ = std::fs::remove_file(&path);
+
+    let (path, server) = fake_hypr_server("confirm-unchanged", vec![before, before]);
+    let client = HyprClient::with_path(path.clone());
+    let outcome = confirm_or_revert_after(&client, || {}, || unreachable!(), Duration::ZERO);
+    assert_eq!(outcome.unwrap(), ConfirmOutcome::Unchanged);
+    server.join().unwrap();
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn refuse_unrestorable_revert() {
+    use super::hypr_ipc::fake_hypr_server;
+    let before = include_str!("../../example.json");
+    let disabled = before.replace("\"disabled\": false", "\"disabled\": true");
+    let unplugged = before.replace("eDP-1", "DP-1");
+    for (name, snapshot, current, reason) in [
+        (
+            "confirm-disabled",
+            &disabled,
+            before.to_string(),
+            "it has no enabled monitor",
+        ),
+        (
+            "confirm-unplugged",
+            &before.to_string(),
+            unplugged,
+            "eDP-1 is no longer connected",
+        ),
+    ] {
+        let (path, server) = fake_hypr_server(
+            name,
+            vec![snapshot.clone(), current.clone(), current.clone()],
+        );
+        let client = HyprClient::with_path(path.clone());
+        let outcome = confirm_or_revert_after(&client, || {}, || false, Duration::ZERO);
+        assert_eq!(outcome.unwrap(), ConfirmOutcome::Kept(reason.into()));
+        // nothing is sent after the current layout was read
+        assert_eq!(server.join().unwrap().len(), 3);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,118 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{process::Command, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    command::{self, CommandOutcome},
+    monitors::Monitor,
+};
+
+/// Commands of a TOML profile, run after it is applied or once another profile replaces it:
+///
+/// ```toml
+/// [[on_apply]]
+/// base = "swww"
+/// args = ["img", "~/wallpapers/office.png"]
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ProfileHooks {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_apply: Vec<ProfileHook>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_leave: Vec<ProfileHook>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileHook {
+    pub base: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Seconds to wait for the hook before it is killed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+impl ProfileHook {
+    /// Runs the hook and waits for it, at most until its timeout.
+    /// `HYPRDOCK_PROFILE` is set to the profile name and `HYPRDOCK_MONITORS`
+    /// to the comma separated connectors of the profile's outputs.
+    pub fn run(&self, profile: &str, monitors: &[Monitor]) -> CommandOutcome {
+        let names: Vec<&str> = monitors
+            .iter()
+            .map(|monitor| monitor.name.as_str())
+            .collect();
+        let mut command = Command::new(self.base.trim());
+        command
+            .args(&self.args)
+            .env("HYPRDOCK_PROFILE", profile)
+            .env("HYPRDOCK_MONITORS", names.join(","));
+        let timeout = self
+            .timeout
+            .map_or(command::DEFAULT_TIMEOUT, Duration::from_secs);
+        command::execute(command, false, timeout)
+    }
+}
+
+/// Runs the hooks one after another and reports the ones that failed.
+/// They are waited for, so a CLI invocation does not exit before its hooks ran.
+pub fn run_hooks(hooks: Vec<ProfileHook>, profile: String, monitors: Vec<Monitor>) {
+    for hook in hooks {
+        let outcome = hook.run(&profile, &monitors);
+        if !outcome.success() {
+            println!(
+                "Hook {} of profile {} failed: {}",
+                hook.base, profile, outcome
+            );
+        }
+    }
+}
+
+#[test]
+fn hook_environment() {
+    let out = std::env::temp_dir().join(format!("hyprdock-hook-{}", std::process::id()));
+    let hook = ProfileHook {
+        base: "sh".into(),
+        args: vec![
+            "-c".into(),
+            format!(
+                "echo \"$HYPRDOCK_PROFILE $HYPRDOCK_MONITORS\" > {}",
+                out.display()
+            ),
+        ],
+        timeout: None,
+    };
+    let monitors = crate::profiles::ProfileFormat::Json
+        .parse_monitors(include_str!("../../example.json"))
+        .unwrap();
+    assert!(hook.run("office", &monitors).success());
+    assert_eq!(std::fs::read_to_string(&out).unwrap(), "office eDP-1\n");
+    let _ = std::fs::remove_file(out);
+
+    let slow = ProfileHook {
+        base: "sleep".into(),
+        args: vec!["5".into()],
+        timeout: Some(0),
+    };
+    assert!(matches!(
+        slow.run("office", &monitors),
+        CommandOutcome::TimedOut(_)
+    ));
+}
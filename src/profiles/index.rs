@@ -0,0 +1,116 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::monitors::{Monitor, fingerprint::fingerprint};
+
+/// Starts with a dot, so it can never clash with a profile name.
+const INDEX_FILE: &str = ".index.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProfileMeta {
+    pub fingerprint: String,
+    /// One entry per monitor, e.g. `DP-1 (Dell Inc. DELL U2719D 7KXJ8T2)`.
+    pub monitors: Vec<String>,
+    /// Unix timestamp of the last time the profile was applied.
+    pub last_used: Option<u64>,
+}
+
+impl ProfileMeta {
+    pub fn from_monitors(monitors: &[Monitor]) -> Self {
+        Self {
+            // profiles are looked up by the monitors that are active
+            fingerprint: fingerprint(monitors.iter().filter(|monitor| !monitor.disabled).map(
+                |monitor| {
+                    (
+                        monitor.make.as_str(),
+                        monitor.model.as_str(),
+                        monitor.serial.as_str(),
+                    )
+                },
+            )),
+            monitors: monitors
+                .iter()
+                .map(|monitor| {
+                    let identity: Vec<&str> = [&monitor.make, &monitor.model, &monitor.serial]
+                        .into_iter()
+                        .map(|field| field.as_str())
+                        .filter(|field| !field.is_empty())
+                        .collect();
+                    format!("{} ({})", monitor.name, identity.join(" "))
+                })
+                .collect(),
+            last_used: None,
+        }
+    }
+}
+
+/// Metadata of all profiles, stored next to them in `monitor_configs/`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ProfileIndex {
+    pub profiles: BTreeMap<String, ProfileMeta>,
+    /// The profile applied last, its `on_leave` hooks run once another one is applied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<String>,
+    /// Set once profiles saved with the legacy hash have been migrated.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub migrated: bool,
+}
+
+impl ProfileIndex {
+    /// A missing or broken index is treated as empty, it is rebuilt from the profiles.
+    pub fn load(dir: &Path) -> Self {
+        fs::read_to_string(dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        let contents = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(dir.join(INDEX_FILE), contents)
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// Formats a timestamp relative to now, e.g. `3h ago`.
+pub fn format_last_used(last_used: Option<u64>) -> String {
+    let Some(last_used) = last_used else {
+        return "never".into();
+    };
+    let elapsed = now().saturating_sub(last_used);
+    match elapsed {
+        0..60 => "just now".into(),
+        60..3600 => format!("{}m ago", elapsed / 60),
+        3600..86400 => format!("{}h ago", elapsed / 3600),
+        _ => format!("{}d ago", elapsed / 86400),
+    }
+}
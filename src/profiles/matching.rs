@@ -0,0 +1,359 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::monitors::Monitor;
+
+/// Rules of a TOML profile for connected monitors it should apply to,
+/// even if their fingerprint differs from the one it was saved for:
+///
+/// ```toml
+/// [match]
+/// monitors = 2
+///
+/// [[match.monitor]]
+/// make = "Dell Inc."
+/// model = "DELL U27*"
+/// ```
+///
+/// Every rule has to match a different connected monitor.
+/// Omitted fields match anything, `*` and `?` can be used as wildcards.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MatchRules {
+    /// Exact number of connected monitors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitors: Option<usize>,
+    #[serde(default, rename = "monitor", skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<MonitorRule>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MonitorRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub make: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+}
+
+impl MonitorRule {
+    /// Specificity of the rule for the given monitor, `None` if it does not match.
+    /// Literal fields count more than patterns, omitted fields and `*` count nothing.
+    fn score(&self, monitor: &Monitor) -> Option<u32> {
+        let mut score = 1;
+        for (pattern, value) in [
+            (&self.name, &monitor.name),
+            (&self.make, &monitor.make),
+            (&self.model, &monitor.model),
+            (&self.serial, &monitor.serial),
+        ] {
+            let Some(pattern) = pattern else {
+                continue;
+            };
+            if !glob_match(pattern, value) {
+                return None;
+            }
+            score += pattern_score(pattern);
+        }
+        Some(score)
+    }
+}
+
+impl MatchRules {
+    /// Specificity of the rules for the connected monitors, higher is more specific.
+    /// Returns `None` if the rules do not match.
+    pub fn score(&self, monitors: &[Monitor]) -> Option<u32> {
+        let mut score = 0;
+        if let Some(count) = self.monitors {
+            if count != monitors.len() {
+                return None;
+            }
+            score += 1;
+        }
+        let mut used = vec![false; monitors.len()];
+        Some(score + self.assign(0, monitors, &mut used)?.0)
+    }
+
+    /// Moves the outputs of a profile to the connectors of the monitors the rules matched.
+    /// Outputs are saved under the connector they had back then, but a matched monitor
+    /// may be plugged into a different one now. Every rule is paired with the output it
+    /// matches in the profile and the connected monitor it matches.
+    /// Unmatched outputs saved under a connector that is now taken by a matched one
+    /// move to the connectors freed by the matched outputs, so no two outputs share one.
+    pub fn remap_outputs(&self, outputs: &mut [Monitor], connected: &[Monitor]) {
+        let (Some((_, saved)), Some((_, matched))) = (
+            self.assign(0, outputs, &mut vec![false; outputs.len()]),
+            self.assign(0, connected, &mut vec![false; connected.len()]),
+        ) else {
+            return;
+        };
+        let mut connectors: BTreeMap<String, String> = saved
+            .into_iter()
+            .zip(matched)
+            .map(|(output, monitor)| {
+                (
+                    outputs[output].name.clone(),
+                    connected[monitor].name.clone(),
+                )
+            })
+            .collect();
+        let freed: Vec<String> = connectors
+            .keys()
+            .filter(|name| !connectors.values().any(|connector| connector == *name))
+            .cloned()
+            .collect();
+        let taken: Vec<String> = connectors
+            .values()
+            .filter(|connector| !connectors.contains_key(*connector))
+            .cloned()
+            .collect();
+        connectors.extend(taken.into_iter().zip(freed));
+        for output in outputs.iter_mut() {
+            if let Some(connector) = connectors.get(&output.name) {
+                output.name = connector.clone();
+            }
+            if let Some(mirror) = output.mirror.as_mut()
+                && let Some(connector) = connectors.get(mirror)
+            {
+                *mirror = connector.clone();
+            }
+        }
+    }
+
+    /// Finds the best scoring assignment of rules to distinct monitors,
+    /// along with the index of the monitor each rule was assigned to.
+    /// Profiles only have a handful of rules, so trying every assignment is fine.
+    fn assign(
+        &self,
+        rule: usize,
+        monitors: &[Monitor],
+        used: &mut [bool],
+    ) -> Option<(u32, Vec<usize>)> {
+        let Some(current) = self.rules.get(rule) else {
+            return Some((0, Vec::new()));
+        };
+        let mut best: Option<(u32, Vec<usize>)> = None;
+        for (index, monitor) in monitors.iter().enumerate() {
+            if used[index] {
+                continue;
+            }
+            let Some(score) = current.score(monitor) else {
+                continue;
+            };
+            used[index] = true;
+            if let Some((rest, mut assigned)) = self.assign(rule + 1, monitors, used)
+                && best
+                    .as_ref()
+                    .is_none_or(|(best_score, _)| score + rest > *best_score)
+            {
+                assigned.insert(0, index);
+                best = Some((score + rest, assigned));
+            }
+            used[index] = false;
+        }
+        best
+    }
+}
+
+fn pattern_score(pattern: &str) -> u32 {
+    if !pattern.contains(['*', '?']) {
+        2
+    } else if pattern.chars().all(|c| c == '*') {
+        0
+    } else {
+        1
+    }
+}
+
+/// Matches `value` against a pattern where `*` matches any sequence
+/// and `?` a single character.
+pub fn glob_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    v = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+pub fn test_monitor(name: &str, make: &str, model: &str, serial: &str) -> Monitor {
+    Monitor {
+        name: name.into(),
+        make: make.into(),
+        model: model.into(),
+        serial: serial.into(),
+        mode: "1920x1080@60".parse().unwrap(),
+        position: Default::default(),
+        scale: Default::default(),
+        transform: Default::default(),
+        available_modes: Vec::new(),
+        vrr: false,
+        disabled: false,
+        mirror: None,
+        bitdepth: None,
+    }
+}
+
+#[test]
+fn glob_patterns() {
+    assert!(glob_match("DELL U27*", "DELL U2719D"));
+    assert!(glob_match("*", ""));
+    assert!(glob_match("D?LL*D", "DELL U2719D"));
+    assert!(glob_match("*19*", "DELL U2719D"));
+    assert!(!glob_match("DELL", "DELL U2719D"));
+    assert!(!glob_match("*U28*", "DELL U2719D"));
+}
+
+#[test]
+fn match_scoring() {
+    let monitors = [
+        test_monitor("eDP-1", "BOE", "0x095F", ""),
+        test_monitor("DP-3", "Dell Inc.", "DELL U2719D", "AAA111"),
+        test_monitor("DP-4", "Dell Inc.", "DELL U2719D", "BBB222"),
+    ];
+    let rule = |make: &str, model: Option<&str>, serial: Option<&str>| MonitorRule {
+        make: Some(make.into()),
+        model: model.map(Into::into),
+        serial: serial.map(Into::into),
+        ..Default::default()
+    };
+    let two_dells = MatchRules {
+        monitors: None,
+        rules: vec![
+            rule("Dell Inc.", Some("DELL U27*"), None),
+            rule("Dell Inc.", Some("DELL U27*"), None),
+        ],
+    };
+    let serial = MatchRules {
+        monitors: Some(3),
+        rules: vec![rule("Dell Inc.", None, Some("BBB222"))],
+    };
+    let three_dells = MatchRules {
+        rules: vec![rule("Dell*", None, None); 3],
+        ..Default::default()
+    };
+    let any_three = MatchRules {
+        monitors: Some(3),
+        rules: Vec::new(),
+    };
+
+    assert_eq!(two_dells.score(&monitors), Some(8));
+    assert_eq!(serial.score(&monitors), Some(6));
+    assert_eq!(three_dells.score(&monitors), None);
+    assert_eq!(any_three.score(&monitors), Some(1));
+    assert_eq!(any_three.score(&monitors[..2]), None);
+}
+
+#[test]
+fn remap_swapped_connectors() {
+    let mut outputs = vec![
+        test_monitor("DP-1", "Dell Inc.", "DELL U2719D", "AAA111"),
+        test_monitor("DP-2", "Dell Inc.", "DELL U2719D", "BBB222"),
+        test_monitor("eDP-1", "BOE", "0x095F", ""),
+    ];
+    outputs[0].position = "0x0".parse().unwrap();
+    outputs[1].position = "2560x0".parse().unwrap();
+    outputs[2].mirror = Some("DP-1".into());
+    let connected = [
+        test_monitor("eDP-1", "BOE", "0x095F", ""),
+        test_monitor("DP-1", "Dell Inc.", "DELL U2719D", "BBB222"),
+        test_monitor("DP-2", "Dell Inc.", "DELL U2719D", "AAA111"),
+    ];
+    let serial = |serial: &str| MonitorRule {
+        serial: Some(serial.into()),
+        ..Default::default()
+    };
+    let rules = MatchRules {
+        monitors: None,
+        rules: vec![serial("AAA111"), serial("BBB222")],
+    };
+    rules.remap_outputs(&mut outputs, &connected);
+
+    let layout: Vec<(&str, &str, String)> = outputs
+        .iter()
+        .map(|output| {
+            (
+                output.name.as_str(),
+                output.serial.as_str(),
+                output.position.to_string(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        layout,
+        vec![
+            ("DP-2", "AAA111", "0x0".into()),
+            ("DP-1", "BBB222", "2560x0".into()),
+            ("eDP-1", "", "0x0".into()),
+        ]
+    );
+    assert_eq!(outputs[2].mirror.as_deref(), Some("DP-2"));
+}
+
+#[test]
+fn remap_unmatched_output() {
+    let mut outputs = vec![
+        test_monitor("DP-1", "Dell Inc.", "DELL U2719D", "AAA111"),
+        test_monitor("DP-2", "LG", "27GL850", "CCC333"),
+    ];
+    outputs[1].mirror = Some("DP-1".into());
+    let connected = [
+        test_monitor("DP-1", "LG", "27GL850", "CCC333"),
+        test_monitor("DP-2", "Dell Inc.", "DELL U2719D", "AAA111"),
+    ];
+    let rules = MatchRules {
+        monitors: None,
+        rules: vec![MonitorRule {
+            make: Some("Dell Inc.".into()),
+            ..Default::default()
+        }],
+    };
+    rules.remap_outputs(&mut outputs, &connected);
+
+    // the unmatched output moves out of the way instead of sharing a connector
+    assert_eq!(outputs[0].name, "DP-2");
+    assert_eq!(outputs[1].name, "DP-1");
+    assert_eq!(outputs[1].mirror.as_deref(), Some("DP-2"));
+}
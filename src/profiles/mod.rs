@@ -0,0 +1,581 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use hooks::ProfileHooks;
+use index::{ProfileIndex, ProfileMeta, format_last_used, now};
+use matching::MatchRules;
+use toml_profile::TomlProfile;
+
+use crate::monitors::{Monitor, hypr_monitors::HyprMonitor};
+
+pub mod hooks;
+pub mod index;
+pub mod matching;
+pub mod toml_profile;
+
+const PROFILE_DIR: &str = "monitor_configs";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProfileFormat {
+    /// Raw dump of hyprland's monitor data.
+    #[default]
+    Json,
+    /// Curated, hand editable format, see [`TomlProfile`].
+    Toml,
+}
+
+impl ProfileFormat {
+    /// Lookup order if a profile exists in both formats.
+    const ALL: [ProfileFormat; 2] = [ProfileFormat::Toml, ProfileFormat::Json];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(ProfileFormat::Json),
+            "toml" => Some(ProfileFormat::Toml),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ProfileFormat::Json => "json",
+            ProfileFormat::Toml => "toml",
+        }
+    }
+
+    fn from_path(path: &Path) -> Option<Self> {
+        Self::from_name(path.extension()?.to_str()?)
+    }
+
+    pub fn parse_monitors(self, contents: &str) -> Result<Vec<Monitor>, ProfileError> {
+        match self {
+            ProfileFormat::Json => {
+                let monitors: Vec<HyprMonitor> = serde_json::from_str(contents)
+                    .map_err(|err| ProfileError::Invalid(err.to_string()))?;
+                Ok(monitors
+                    .iter()
+                    .map(|monitor| monitor.convert_data())
+                    .collect())
+            }
+            ProfileFormat::Toml => TomlProfile::parse(contents)
+                .map_err(|err| ProfileError::Invalid(err.to_string()))?
+                .to_monitors()
+                .map_err(|err| ProfileError::Invalid(err.to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ProfileError {
+    InvalidName(String),
+    NotFound(String),
+    AlreadyExists(String),
+    Invalid(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::InvalidName(name) => write!(
+                f,
+                "Invalid profile name \"{}\", names must not be empty or contain '/' or '..'",
+                name
+            ),
+            ProfileError::NotFound(name) => write!(f, "Profile {} does not exist", name),
+            ProfileError::AlreadyExists(name) => write!(f, "Profile {} already exists", name),
+            ProfileError::Invalid(err) => write!(f, "Invalid profile: {}", err),
+            ProfileError::Io(err) => write!(f, "Could not access profile: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+impl From<io::Error> for ProfileError {
+    fn from(err: io::Error) -> Self {
+        ProfileError::Io(err)
+    }
+}
+
+/// Saved monitor configurations in `monitor_configs/`.
+/// Profiles are stored as `<key>.json` or `<key>.toml`, where the key is either
+/// a user given name or the fingerprint of the monitors the profile was saved for.
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+impl ProfileStore {
+    /// Store inside the hyprdock config directory, i.e. `monitor_config_path`.
+    pub fn new(base_path: impl AsRef<Path>) -> Self {
+        Self::with_dir(base_path.as_ref().join(PROFILE_DIR))
+    }
+
+    pub fn with_dir(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn validate_name(name: &str) -> Result<(), ProfileError> {
+        if name.is_empty()
+            || name.starts_with('.')
+            || name.contains(['/', '\0'])
+            || name.contains("..")
+        {
+            return Err(ProfileError::InvalidName(name.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn path_for(&self, key: &str, format: ProfileFormat) -> Result<PathBuf, ProfileError> {
+        Self::validate_name(key)?;
+        Ok(self.dir.join(format!("{}.{}", key, format.extension())))
+    }
+
+    /// Returns the path of an existing profile in either format.
+    pub fn find(&self, key: &str) -> Option<PathBuf> {
+        ProfileFormat::ALL.into_iter().find_map(|format| {
+            let path = self.path_for(key, format).ok()?;
+            if path.is_file() { Some(path) } else { None }
+        })
+    }
+
+    /// Saves a profile, replacing an existing one with the same key in any format.
+    pub fn save(
+        &self,
+        key: &str,
+        contents: &str,
+        format: ProfileFormat,
+    ) -> Result<PathBuf, ProfileError> {
+        let path = self.path_for(key, format)?;
+        let monitors = format.parse_monitors(contents)?;
+        fs::create_dir_all(&self.dir)?;
+        if let Some(existing) = self.find(key)
+            && existing != path
+        {
+            fs::remove_file(existing)?;
+        }
+        fs::write(&path, contents)?;
+        let mut index = ProfileIndex::load(&self.dir);
+        let last_used = index.profiles.get(key).and_then(|meta| meta.last_used);
+        index.profiles.insert(
+            key.to_string(),
+            ProfileMeta {
+                last_used,
+                ..ProfileMeta::from_monitors(&monitors)
+            },
+        );
+        index.save(&self.dir)?;
+        Ok(path)
+    }
+
+    /// Records that a profile has just been applied.
+    pub fn mark_used(&self, key: &str) -> Result<(), ProfileError> {
+        let mut index = ProfileIndex::load(&self.dir);
+        let meta = match index.profiles.remove(key) {
+            Some(meta) => meta,
+            None => self.describe(key)?,
+        };
+        index.profiles.insert(
+            key.to_string(),
+            ProfileMeta {
+                last_used: Some(now()),
+                ..meta
+            },
+        );
+        index.active = Some(key.to_string());
+        Ok(index.save(&self.dir)?)
+    }
+
+    /// The profile applied last, if it still exists.
+    pub fn active(&self) -> Option<String> {
+        ProfileIndex::load(&self.dir)
+            .active
+            .filter(|key| self.find(key).is_some())
+    }
+
+    /// Whether legacy profiles have already been migrated, see `migrate_monitor_configs`.
+    pub fn migrated(&self) -> bool {
+        ProfileIndex::load(&self.dir).migrated
+    }
+
+    pub fn mark_migrated(&self) -> Result<(), ProfileError> {
+        let mut index = ProfileIndex::load(&self.dir);
+        index.migrated = true;
+        Ok(index.save(&self.dir)?)
+    }
+
+    pub fn clear_active(&self) -> Result<(), ProfileError> {
+        let mut index = ProfileIndex::load(&self.dir);
+        if index.active.take().is_some() {
+            index.save(&self.dir)?;
+        }
+        Ok(())
+    }
+
+    /// Metadata of a profile, as recorded in the index or derived from the profile itself.
+    pub fn describe(&self, key: &str) -> Result<ProfileMeta, ProfileError> {
+        let monitors = self.load_monitors(key)?;
+        if let Some(meta) = ProfileIndex::load(&self.dir).profiles.remove(key) {
+            return Ok(meta);
+        }
+        Ok(ProfileMeta::from_monitors(&monitors))
+    }
+
+    /// Names of all saved profiles, sorted.
+    pub fn names(&self) -> Result<Vec<String>, ProfileError> {
+        let mut names = Vec::new();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(names),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if ProfileFormat::from_path(&path).is_none() {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str())
+                && Self::validate_name(name).is_ok()
+            {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    pub fn rename(&self, from: &str, to: &str) -> Result<(), ProfileError> {
+        let (source, target) = self.prepare_target(from, to)?;
+        fs::rename(source, target)?;
+        let mut index = ProfileIndex::load(&self.dir);
+        if let Some(meta) = index.profiles.remove(from) {
+            index.profiles.insert(to.to_string(), meta);
+        }
+        if index.active.as_deref() == Some(from) {
+            index.active = Some(to.to_string());
+        }
+        Ok(index.save(&self.dir)?)
+    }
+
+    pub fn copy(&self, from: &str, to: &str) -> Result<(), ProfileError> {
+        let (source, target) = self.prepare_target(from, to)?;
+        fs::copy(source, target)?;
+        let mut index = ProfileIndex::load(&self.dir);
+        if let Some(meta) = index.profiles.get(from).cloned() {
+            index.profiles.insert(
+                to.to_string(),
+                ProfileMeta {
+                    last_used: None,
+                    ..meta
+                },
+            );
+        }
+        Ok(index.save(&self.dir)?)
+    }
+
+    pub fn delete(&self, key: &str) -> Result<(), ProfileError> {
+        let path = self
+            .find(key)
+            .ok_or_else(|| ProfileError::NotFound(key.to_string()))?;
+        fs::remove_file(path)?;
+        let mut index = ProfileIndex::load(&self.dir);
+        index.profiles.remove(key);
+        if index.active.as_deref() == Some(key) {
+            index.active = None;
+        }
+        Ok(index.save(&self.dir)?)
+    }
+
+    /// Returns the source path and the target path in the same format.
+    fn prepare_target(&self, from: &str, to: &str) -> Result<(PathBuf, PathBuf), ProfileError> {
+        let source = self
+            .find(from)
+            .ok_or_else(|| ProfileError::NotFound(from.to_string()))?;
+        Self::validate_name(to)?;
+        if self.find(to).is_some() {
+            return Err(ProfileError::AlreadyExists(to.to_string()));
+        }
+        let format = ProfileFormat::from_path(&source).unwrap_or_default();
+        let target = self.path_for(to, format)?;
+        Ok((source, target))
+    }
+
+    /// Raw contents of a profile and the format it is stored in.
+    pub fn load(&self, key: &str) -> Result<(String, ProfileFormat), ProfileError> {
+        let path = self
+            .find(key)
+            .ok_or_else(|| ProfileError::NotFound(key.to_string()))?;
+        let format = ProfileFormat::from_path(&path).unwrap_or_default();
+        Ok((fs::read_to_string(path)?, format))
+    }
+
+    /// Finds the TOML profile whose `[match]` rules fit the monitors best.
+    /// Ties are resolved by name, so the choice is stable.
+    pub fn best_match(&self, monitors: &[Monitor]) -> Result<Option<String>, ProfileError> {
+        let mut best: Option<(u32, String)> = None;
+        for name in self.names()? {
+            let Ok((contents, ProfileFormat::Toml)) = self.load(&name) else {
+                continue;
+            };
+            let rules = match TomlProfile::parse(&contents) {
+                Ok(TomlProfile {
+                    matching: Some(rules),
+                    ..
+                }) => rules,
+                Ok(_) => continue,
+                Err(err) => {
+                    eprintln!("Skipping profile {}: {}", name, err);
+                    continue;
+                }
+            };
+            if let Some(score) = rules.score(monitors)
+                && best
+                    .as_ref()
+                    .is_none_or(|(best_score, _)| score > *best_score)
+            {
+                best = Some((score, name));
+            }
+        }
+        Ok(best.map(|(_, name)| name))
+    }
+
+    pub fn load_monitors(&self, key: &str) -> Result<Vec<Monitor>, ProfileError> {
+        let (contents, format) = self.load(key)?;
+        format.parse_monitors(&contents)
+    }
+
+    /// `[match]` rules of a profile, raw hyprland dumps can not have any.
+    pub fn load_rules(&self, key: &str) -> Result<Option<MatchRules>, ProfileError> {
+        match self.load(key)? {
+            (contents, ProfileFormat::Toml) => Ok(TomlProfile::parse(&contents)
+                .map_err(|err| ProfileError::Invalid(err.to_string()))?
+                .matching),
+            (_, ProfileFormat::Json) => Ok(None),
+        }
+    }
+
+    /// Hooks of a profile, raw hyprland dumps can not have any.
+    pub fn load_hooks(&self, key: &str) -> Result<ProfileHooks, ProfileError> {
+        match self.load(key)? {
+            (contents, ProfileFormat::Toml) => Ok(TomlProfile::parse(&contents)
+                .map_err(|err| ProfileError::Invalid(err.to_string()))?
+                .hooks),
+            (_, ProfileFormat::Json) => Ok(ProfileHooks::default()),
+        }
+    }
+}
+
+fn print_profile(name: &str, meta: &ProfileMeta) {
+    println!("{}", name);
+    println!("  fingerprint: {}", meta.fingerprint);
+    println!("  monitors:    {}", meta.monitors.join(", "));
+    println!("  last used:   {}", format_last_used(meta.last_used));
+}
+
+/// Handles `hyprdock profile <command>`.
+pub fn run_profile_command(base_path: &str, args: &[&String]) -> ExitCode {
+    let store = ProfileStore::new(base_path);
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    let res = match args.as_slice() {
+        ["list"] => store.names().map(|names| {
+            for name in names {
+                match store.describe(&name) {
+                    Ok(meta) => print_profile(&name, &meta),
+                    Err(err) => println!("{}: {}", name, err),
+                }
+            }
+        }),
+        ["show", name] => store.describe(name).and_then(|meta| {
+            print_profile(name, &meta);
+            println!();
+            print!("{}", store.load(name)?.0);
+            Ok(())
+        }),
+        ["rename", from, to] => store.rename(from, to),
+        ["copy", from, to] => store.copy(from, to),
+        ["delete", name] => store.delete(name),
+        _ => {
+            print_profile_help();
+            return ExitCode::FAILURE;
+        }
+    };
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            println!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+pub fn print_profile_help() {
+    print!(
+        "Possible profile commands are:
+            list:                 List all saved monitor configs
+            show <name>:          Show a monitor config
+            rename <name> <new>:  Rename a monitor config
+            copy <name> <new>:    Copy a monitor config
+            delete <name>:        Delete a monitor config\n"
+    );
+}
+
+#[cfg(test)]
+pub fn test_store(name: &str) -> (PathBuf, ProfileStore) {
+    let base = std::env::temp_dir().join(format!("hyprdock-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let store = ProfileStore::new(&base);
+    (base, store)
+}
+
+#[test]
+fn profile_store_round_trip() {
+    use crate::monitors::hypr_monitors::try_get_monitor_hash_path;
+    let (base, store) = test_store("profiles");
+    let contents = include_str!("../../example.json");
+    let fingerprint = "v1-bd56df9b747166a38de6b261ee06660e07909915c60eedf81312e4697bd1b8ea";
+
+    for key in ["work", fingerprint] {
+        assert!(store.find(key).is_none());
+        let path = store.save(key, contents, ProfileFormat::Json).unwrap();
+        assert_eq!(
+            path,
+            base.join("monitor_configs").join(format!("{}.json", key))
+        );
+        assert_eq!(store.load(key).unwrap().0, contents);
+        // the daemon looks profiles up through the same store
+        assert_eq!(
+            try_get_monitor_hash_path(base.to_str().unwrap().to_string(), key),
+            Some(path)
+        );
+    }
+    assert!(matches!(store.load("home"), Err(ProfileError::NotFound(_))));
+    let _ = fs::remove_dir_all(base);
+}
+
+#[test]
+fn profile_store_rejects_paths() {
+    let store = ProfileStore::with_dir(PathBuf::from("/nonexistent"));
+    for name in ["", "..", "../hyprdock", "a/b", ".hidden", "a..b"] {
+        assert!(matches!(
+            store.save(name, "[]", ProfileFormat::Json),
+            Err(ProfileError::InvalidName(_))
+        ));
+    }
+}
+
+#[test]
+fn profile_store_management() {
+    let (base, store) = test_store("profile-management");
+    let contents = include_str!("../../example.json");
+    store.save("home", contents, ProfileFormat::Json).unwrap();
+    store.mark_used("home").unwrap();
+    assert_eq!(store.active().as_deref(), Some("home"));
+
+    store.copy("home", "work").unwrap();
+    assert!(matches!(
+        store.copy("home", "work"),
+        Err(ProfileError::AlreadyExists(_))
+    ));
+    store.rename("work", "office").unwrap();
+    assert_eq!(store.names().unwrap(), vec!["home", "office"]);
+
+    let home = store.describe("home").unwrap();
+    let office = store.describe("office").unwrap();
+    assert_eq!(home.fingerprint, office.fingerprint);
+    assert_eq!(home.monitors, vec!["eDP-1 (Samsung Display Corp. 0x4174)"]);
+    assert!(home.last_used.is_some());
+    assert!(office.last_used.is_none());
+
+    store.delete("home").unwrap();
+    assert_eq!(store.names().unwrap(), vec!["office"]);
+    assert_eq!(store.active(), None);
+    assert!(matches!(
+        store.delete("home"),
+        Err(ProfileError::NotFound(_))
+    ));
+    let _ = fs::remove_dir_all(base);
+}
+
+#[test]
+fn profile_store_formats() {
+    let (base, store) = test_store("profile-formats");
+    store
+        .save(
+            "desk",
+            include_str!("../../example.json"),
+            ProfileFormat::Json,
+        )
+        .unwrap();
+    let toml = "[[output]]\nname = \"eDP-1\"\nmode = \"3840x2400@60\"\nscale = 2.0\n";
+    let path = store.save("desk", toml, ProfileFormat::Toml).unwrap();
+    assert_eq!(store.find("desk"), Some(path));
+    assert_eq!(store.names().unwrap(), vec!["desk"]);
+
+    let monitors = store.load_monitors("desk").unwrap();
+    assert_eq!(monitors[0].mode.to_string(), "3840x2400@60");
+    store.copy("desk", "desk2").unwrap();
+    assert_eq!(store.load("desk2").unwrap().1, ProfileFormat::Toml);
+    assert!(matches!(
+        store.save("broken", "[[output]]", ProfileFormat::Toml),
+        Err(ProfileError::Invalid(_))
+    ));
+    let _ = fs::remove_dir_all(base);
+}
+
+#[test]
+fn profile_store_best_match() {
+    let (base, store) = test_store("profile-match");
+    let monitors = ProfileFormat::Json
+        .parse_monitors(include_str!("../../example.json"))
+        .unwrap();
+    let profile = |rules: &str| {
+        format!(
+            "{}\n[[output]]\nname = \"eDP-1\"\nmode = \"1920x1080@60\"\n",
+            rules
+        )
+    };
+    store
+        .save("any", &profile("[match]"), ProfileFormat::Toml)
+        .unwrap();
+    store
+        .save(
+            "count",
+            &profile("[match]\nmonitors = 1"),
+            ProfileFormat::Toml,
+        )
+        .unwrap();
+    store
+        .save("unmatched", &profile(""), ProfileFormat::Toml)
+        .unwrap();
+    assert_eq!(
+        store.best_match(&monitors).unwrap().as_deref(),
+        Some("count")
+    );
+    assert_eq!(store.best_match(&[]).unwrap().as_deref(), Some("any"));
+    let _ = fs::remove_dir_all(base);
+}
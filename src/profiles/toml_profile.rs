@@ -0,0 +1,284 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use super::{hooks::ProfileHooks, matching::MatchRules};
+use crate::monitors::{
+    Monitor,
+    model::{Mode, ModelError, Position, Scale, Transform},
+};
+
+/// Hand editable profile, stored as `<name>.toml`.
+/// Unlike the raw hyprland dump, it only contains what is needed to restore the layout:
+///
+/// ```toml
+/// [[output]]
+/// name = "DP-1"
+/// make = "Dell Inc."
+/// model = "DELL U2719D"
+/// serial = "7KXJ8T2"
+/// mode = "2560x1440@59.951"
+/// position = "0x0"
+/// scale = 1.0
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct TomlProfile {
+    /// Applies the profile to monitors other than the ones it was saved for.
+    #[serde(default, rename = "match", skip_serializing_if = "Option::is_none")]
+    pub matching: Option<MatchRules>,
+    #[serde(flatten)]
+    pub hooks: ProfileHooks,
+    #[serde(default, rename = "output")]
+    pub outputs: Vec<OutputConfig>,
+    /// Keys not known to hyprdock, `deny_unknown_fields` does not work with the flattened hooks.
+    #[serde(flatten, skip_serializing)]
+    unknown: toml::Table,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    pub name: String,
+    #[serde(default)]
+    pub make: String,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub serial: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// `<width>x<height>@<refresh rate>`, e.g. `1920x1080@60`, only required if enabled.
+    #[serde(default)]
+    pub mode: String,
+    #[serde(default = "default_position")]
+    pub position: String,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub transform: i64,
+    #[serde(default)]
+    pub vrr: bool,
+    /// Name of the output this one mirrors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<String>,
+    /// Only needed for 10 bit, 8 bit is the default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitdepth: Option<u8>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_position() -> String {
+    "0x0".into()
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl From<&Monitor> for OutputConfig {
+    fn from(monitor: &Monitor) -> Self {
+        Self {
+            name: monitor.name.clone(),
+            make: monitor.make.clone(),
+            model: monitor.model.clone(),
+            serial: monitor.serial.clone(),
+            enabled: !monitor.disabled,
+            mode: monitor.mode.to_string(),
+            position: monitor.position.to_string(),
+            scale: monitor.scale.value(),
+            transform: monitor.transform.value(),
+            vrr: monitor.vrr,
+            mirror: monitor.mirror.clone(),
+            bitdepth: monitor.bitdepth,
+        }
+    }
+}
+
+/// Disabled outputs only need their name, hyprland reports placeholders like `0x0@0` for them.
+/// Their invalid values are replaced by `default` instead of failing.
+fn default_if_disabled<T>(
+    enabled: bool,
+    value: Result<T, ModelError>,
+    default: T,
+) -> Result<T, ModelError> {
+    match value {
+        Err(_) if !enabled => Ok(default),
+        value => value,
+    }
+}
+
+impl OutputConfig {
+    pub fn to_monitor(&self) -> Result<Monitor, ModelError> {
+        let enabled = self.enabled;
+        Ok(Monitor {
+            name: self.name.clone(),
+            make: self.make.clone(),
+            model: self.model.clone(),
+            serial: self.serial.clone(),
+            mode: default_if_disabled(enabled, self.mode.parse(), Mode::from_hz(0, 0, 0.0))?,
+            position: default_if_disabled(enabled, self.position.parse(), Position::default())?,
+            scale: default_if_disabled(enabled, Scale::new(self.scale), Scale::default())?,
+            transform: default_if_disabled(
+                enabled,
+                Transform::try_from(self.transform),
+                Transform::default(),
+            )?,
+            available_modes: Vec::new(),
+            vrr: self.vrr,
+            disabled: !self.enabled,
+            mirror: self.mirror.clone(),
+            bitdepth: self.bitdepth,
+        })
+    }
+}
+
+impl TomlProfile {
+    pub fn new(outputs: Vec<OutputConfig>) -> Self {
+        Self {
+            outputs,
+            ..Default::default()
+        }
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        let profile: Self = toml::from_str(contents)?;
+        if let Some(key) = profile.unknown.keys().next() {
+            return Err(serde::de::Error::custom(format!("unknown field `{}`", key)));
+        }
+        Ok(profile)
+    }
+
+    pub fn to_monitors(&self) -> Result<Vec<Monitor>, ModelError> {
+        self.outputs
+            .iter()
+            .map(|output| output.to_monitor())
+            .collect()
+    }
+}
+
+#[test]
+fn toml_profile_round_trip() {
+    let contents = r#"
+[match]
+monitors = 2
+
+[[match.monitor]]
+make = "Dell Inc."
+model = "DELL U27*"
+
+[[on_apply]]
+base = "swww"
+args = ["img", "~/wallpapers/office.png"]
+
+[[on_leave]]
+base = "notify-send"
+args = ["left office"]
+
+[[output]]
+name = "eDP-1"
+enabled = false
+mode = "2880x1800@120"
+
+[[output]]
+name = "DP-1"
+make = "Dell Inc."
+model = "DELL U2719D"
+serial = "7KXJ8T2"
+mode = "2560x1440@59.951"
+position = "2880x0"
+scale = 1.25
+transform = 1
+vrr = true
+mirror = "HDMI-A-1"
+bitdepth = 10
+"#;
+    let profile = TomlProfile::parse(contents).unwrap();
+    assert_eq!(
+        TomlProfile::parse(&toml::to_string(&profile).unwrap()).unwrap(),
+        profile
+    );
+
+    assert_eq!(profile.matching.as_ref().unwrap().rules.len(), 1);
+    assert_eq!(profile.hooks.on_apply[0].args[1], "~/wallpapers/office.png");
+    assert_eq!(profile.hooks.on_leave.len(), 1);
+
+    let monitors = profile.to_monitors().unwrap();
+    assert!(monitors[0].disabled);
+    assert_eq!(monitors[0].position, Default::default());
+    assert_eq!(monitors[1].mode.to_string(), "2560x1440@59.951");
+    assert_eq!(monitors[1].scale.value(), 1.25);
+    assert_eq!(monitors[1].transform, Transform::Rotate90);
+    assert_eq!(monitors[1].mirror.as_deref(), Some("HDMI-A-1"));
+    assert_eq!(monitors[1].bitdepth, Some(10));
+}
+
+#[test]
+fn toml_profile_disabled_output() {
+    let mut monitors = crate::profiles::ProfileFormat::Json
+        .parse_monitors(include_str!("../../example.json"))
+        .unwrap();
+    // what hyprland reports for a disabled output
+    monitors[0].disabled = true;
+    monitors[0].mode = Mode::from_hz(0, 0, 0.0);
+    monitors[0].scale = Scale::default();
+    let profile = TomlProfile {
+        outputs: monitors.iter().map(OutputConfig::from).collect(),
+        ..Default::default()
+    };
+    let contents = toml::to_string(&profile).unwrap();
+    assert!(contents.contains("mode = \"0x0@0\""));
+    let restored = TomlProfile::parse(&contents)
+        .unwrap()
+        .to_monitors()
+        .unwrap();
+    assert!(restored[0].disabled);
+    assert_eq!(restored[0].name, monitors[0].name);
+    assert_eq!(
+        restored[0].hypr_rule(),
+        format!("{},disabled", monitors[0].name)
+    );
+
+    let without_mode = "[[output]]\nname = \"eDP-1\"\nenabled = false\n";
+    assert!(
+        TomlProfile::parse(without_mode)
+            .unwrap()
+            .to_monitors()
+            .unwrap()[0]
+            .disabled
+    );
+    let enabled = "[[output]]\nname = \"eDP-1\"\nmode = \"0x0@0\"\n";
+    assert!(TomlProfile::parse(enabled).unwrap().to_monitors().is_err());
+}
+
+#[test]
+fn toml_profile_unknown_fields() {
+    let output = "[[output]]\nname = \"DP-1\"\nmode = \"1920x1080@60\"\n";
+    assert!(TomlProfile::parse(output).is_ok());
+    for typo in [
+        "[[output]]\nname = \"DP-1\"\npostion = \"0x0\"\n",
+        "[[output]]\nname = \"DP-1\"\nscael = 2.0\n",
+        "[[on_aply]]\nbase = \"true\"\n",
+        "[[on_apply]]\nbase = \"true\"\narg = [\"x\"]\n",
+    ] {
+        let err = TomlProfile::parse(&format!("{}{}", output, typo)).unwrap_err();
+        assert!(err.to_string().contains("unknown field"), "{}", err);
+    }
+}
@@ -0,0 +1,310 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{
+    HyprDock,
+    control::{self, ControlRequest},
+    events::logind,
+    inhibit::{InhibitState, Inhibitor},
+    monitors::{
+        Monitor,
+        hypr_monitors::{find_matching_profile, get_all_hypr_monitors, get_current_monitor_hash},
+    },
+};
+
+const PROC_LID_DIR: &str = "/proc/acpi/button/lid";
+/// The daemon answers right away, unless it is busy with an action,
+/// e.g. waiting for a changed layout to be confirmed.
+const DAEMON_STATUS_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LidState {
+    Open,
+    Closed,
+    Unknown,
+}
+
+/// The layout hyprdock considers active, derived from the enabled monitors.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DockMode {
+    Internal,
+    External,
+    Extend,
+    Mirror,
+    None,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DaemonState {
+    pub running: bool,
+    /// Running, but did not answer in time.
+    pub busy: bool,
+    pub inhibit: Option<InhibitState>,
+}
+
+/// Output of `hyprdock status`, the JSON variant is meant for status bars.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Status {
+    pub lid: LidState,
+    pub internal_monitor: String,
+    pub internal_active: bool,
+    pub externals: Vec<String>,
+    pub mode: DockMode,
+    pub profile: Option<String>,
+    pub fingerprint: Option<String>,
+    pub daemon: DaemonState,
+}
+
+impl fmt::Display for LidState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            LidState::Open => "open",
+            LidState::Closed => "closed",
+            LidState::Unknown => "unknown",
+        };
+        write!(f, "{}", state)
+    }
+}
+
+impl fmt::Display for DockMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self {
+            DockMode::Internal => "internal",
+            DockMode::External => "external",
+            DockMode::Extend => "extend",
+            DockMode::Mirror => "mirror",
+            DockMode::None => "none",
+        };
+        write!(f, "{}", mode)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "lid:         {}", self.lid)?;
+        writeln!(
+            f,
+            "internal:    {} ({})",
+            self.internal_monitor,
+            if self.internal_active {
+                "active"
+            } else {
+                "inactive"
+            }
+        )?;
+        if self.externals.is_empty() {
+            writeln!(f, "externals:   none")?;
+        } else {
+            writeln!(f, "externals:   {}", self.externals.join(", "))?;
+        }
+        writeln!(f, "mode:        {}", self.mode)?;
+        writeln!(
+            f,
+            "profile:     {}",
+            self.profile.as_deref().unwrap_or("none")
+        )?;
+        if let Some(fingerprint) = &self.fingerprint {
+            writeln!(f, "fingerprint: {}", fingerprint)?;
+        }
+        let daemon = match (self.daemon.running, self.daemon.busy) {
+            (true, true) => "running (busy)",
+            (true, false) => "running",
+            (false, _) => "not running",
+        };
+        match &self.daemon.inhibit {
+            Some(inhibit) => write!(f, "daemon:      {}, {}", daemon, inhibit.describe()),
+            None => write!(f, "daemon:      {}", daemon),
+        }
+    }
+}
+
+/// Reads the lid state from `/proc/acpi/button/lid/*/state`,
+/// which contains a line like `state:      open`.
+fn proc_lid_state(dir: &Path) -> Option<LidState> {
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let Ok(contents) = fs::read_to_string(entry.path().join("state")) else {
+            continue;
+        };
+        match contents.split_whitespace().last() {
+            Some("open") => return Some(LidState::Open),
+            Some("closed") => return Some(LidState::Closed),
+            _ => continue,
+        }
+    }
+    None
+}
+
+pub fn lid_state() -> LidState {
+    proc_lid_state(Path::new(PROC_LID_DIR))
+        .or_else(|| {
+            logind::query_lid_closed().map(|closed| {
+                if closed {
+                    LidState::Closed
+                } else {
+                    LidState::Open
+                }
+            })
+        })
+        .unwrap_or(LidState::Unknown)
+}
+
+/// Same conditions as `is_internal_active` and `has_external_monitor`.
+pub fn dock_mode(monitors: &[Monitor], internal_monitor: &str) -> DockMode {
+    let enabled: Vec<&Monitor> = monitors
+        .iter()
+        .filter(|monitor| !monitor.disabled)
+        .collect();
+    let internal_active = enabled
+        .iter()
+        .any(|monitor| monitor.name == internal_monitor);
+    let has_external = enabled
+        .iter()
+        .any(|monitor| monitor.name != internal_monitor);
+    let mirrored = enabled.iter().any(|monitor| monitor.mirror.is_some());
+    match (internal_active, has_external) {
+        (false, false) => DockMode::None,
+        (true, false) => DockMode::Internal,
+        (false, true) => DockMode::External,
+        (true, true) if mirrored => DockMode::Mirror,
+        (true, true) => DockMode::Extend,
+    }
+}
+
+/// Asks the daemon listening on the control socket for its state.
+/// Without an answer, the inhibition is read from the shared state file instead.
+fn daemon_state(socket: Option<PathBuf>) -> DaemonState {
+    let response = socket.map(|path| {
+        control::send_request(&path, &ControlRequest::Status, Some(DAEMON_STATUS_TIMEOUT))
+    });
+    let (running, busy) = match response {
+        Some(Ok(response)) => {
+            return DaemonState {
+                running: response.ok,
+                busy: false,
+                inhibit: response.status.and_then(|status| status.inhibit),
+            };
+        }
+        Some(Err(err))
+            if matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            (true, true)
+        }
+        _ => (false, false),
+    };
+    DaemonState {
+        running,
+        busy,
+        inhibit: Inhibitor::new().and_then(|inhibitor| inhibitor.state()),
+    }
+}
+
+impl HyprDock {
+    /// Diagnostics go to stderr, so that `status --json` only prints JSON.
+    pub fn status(&self) -> Status {
+        let monitors = get_all_hypr_monitors().unwrap_or_else(|err| {
+            eprintln!("Could not get current monitors: {}", err);
+            Vec::new()
+        });
+        let daemon = daemon_state(control::socket_path());
+        Status {
+            lid: lid_state(),
+            internal_monitor: self.monitor_name.clone(),
+            internal_active: monitors
+                .iter()
+                .any(|monitor| monitor.name == self.monitor_name && !monitor.disabled),
+            externals: monitors
+                .iter()
+                .filter(|monitor| monitor.name != self.monitor_name && !monitor.disabled)
+                .map(|monitor| monitor.name.clone())
+                .collect(),
+            mode: dock_mode(&monitors, &self.monitor_name),
+            profile: find_matching_profile(self.monitor_config_path.clone())
+                .ok()
+                .flatten(),
+            fingerprint: get_current_monitor_hash().ok(),
+            daemon,
+        }
+    }
+}
+
+#[test]
+fn status_mode_and_lid() {
+    use crate::profiles::ProfileFormat;
+    let internal = ProfileFormat::Json
+        .parse_monitors(include_str!("../example.json"))
+        .unwrap()
+        .remove(0);
+    let mut external = internal.clone();
+    external.name = "DP-1".into();
+    let mut disabled = internal.clone();
+    disabled.disabled = true;
+    assert_eq!(dock_mode(&[], "eDP-1"), DockMode::None);
+    assert_eq!(
+        dock_mode(std::slice::from_ref(&internal), "eDP-1"),
+        DockMode::Internal
+    );
+    assert_eq!(
+        dock_mode(&[disabled, external.clone()], "eDP-1"),
+        DockMode::External
+    );
+    assert_eq!(
+        dock_mode(&[internal.clone(), external.clone()], "eDP-1"),
+        DockMode::Extend
+    );
+    external.mirror = Some("eDP-1".into());
+    assert_eq!(dock_mode(&[internal, external], "eDP-1"), DockMode::Mirror);
+
+    let dir = std::env::temp_dir().join(format!("hyprdock-lid-{}", std::process::id()));
+    fs::create_dir_all(dir.join("LID0")).unwrap();
+    fs::write(dir.join("LID0/state"), "state:      closed\n").unwrap();
+    assert_eq!(proc_lid_state(&dir), Some(LidState::Closed));
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn status_busy_daemon() {
+    use std::{os::unix::net::UnixListener, thread};
+    let path = std::env::temp_dir().join(format!("hyprdock-busy-{}.sock", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    // accepts the request, but never answers
+    let daemon = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        thread::sleep(DAEMON_STATUS_TIMEOUT * 2);
+        drop(stream);
+    });
+    let state = daemon_state(Some(path.clone()));
+    assert!(state.running && state.busy);
+    daemon.join().unwrap();
+    let _ = fs::remove_file(&path);
+
+    let state = daemon_state(Some(path));
+    assert!(!state.running && !state.busy);
+}
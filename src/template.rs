@@ -0,0 +1,220 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{env, fmt};
+
+/// Values that can be used as `{name}` in command arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    /// The internal monitor, `{}` is kept as a short form.
+    Internal,
+    /// The enabled external monitors, separated by spaces.
+    Externals,
+    FirstExternal,
+    /// The active profile, empty without one.
+    Profile,
+    /// The current layout like `extend` or `internal`.
+    Mode,
+    /// `open`, `closed` or `unknown`.
+    Lid,
+}
+
+impl Placeholder {
+    pub const ALL: [Placeholder; 6] = [
+        Placeholder::Internal,
+        Placeholder::Externals,
+        Placeholder::FirstExternal,
+        Placeholder::Profile,
+        Placeholder::Mode,
+        Placeholder::Lid,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Placeholder::Internal => "internal",
+            Placeholder::Externals => "externals",
+            Placeholder::FirstExternal => "first_external",
+            Placeholder::Profile => "profile",
+            Placeholder::Mode => "mode",
+            Placeholder::Lid => "lid",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.is_empty() {
+            return Some(Placeholder::Internal);
+        }
+        Self::ALL
+            .into_iter()
+            .find(|placeholder| placeholder.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    UnknownPlaceholder(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UnknownPlaceholder(name) => {
+                let known: Vec<String> = Placeholder::ALL
+                    .iter()
+                    .map(|placeholder| format!("{{{}}}", placeholder.name()))
+                    .collect();
+                write!(
+                    f,
+                    "Unknown placeholder {{{}}}, expected one of {}, or {{{{ for a literal {{",
+                    name,
+                    known.join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// Replaces `{name}` placeholders with the value from `lookup`, `{{` is a literal `{`.
+/// Braces that do not enclose a lowercase name, like `{print $1}`, are kept as they are.
+pub fn expand_placeholders(
+    input: &str,
+    lookup: impl Fn(Placeholder) -> String,
+) -> Result<String, TemplateError> {
+    let mut result = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(escaped) = after.strip_prefix('{') {
+            result.push('{');
+            rest = escaped;
+            continue;
+        }
+        let name_len = after
+            .find(|c: char| !(c.is_ascii_lowercase() || c == '_'))
+            .unwrap_or(after.len());
+        if after[name_len..].starts_with('}') {
+            let name = &after[..name_len];
+            let placeholder = Placeholder::from_name(name)
+                .ok_or_else(|| TemplateError::UnknownPlaceholder(name.into()))?;
+            result.push_str(&lookup(placeholder));
+            rest = &after[name_len + 1..];
+        } else {
+            result.push('{');
+            rest = after;
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Quotes a value as a single word for `sh`.
+/// Values made of characters the shell does not interpret, like `eDP-1`, are kept as they are.
+pub fn shell_quote(value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_.,:/@%+=".contains(c);
+    if !value.is_empty() && value.chars().all(safe) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Expands `$NAME` and `${NAME}` from the environment, unset variables are empty like in a shell.
+/// `$$` is a literal `$`.
+pub fn expand_env(input: &str) -> String {
+    let mut result = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(remaining) = after.strip_prefix('$') {
+            result.push('$');
+            rest = remaining;
+            continue;
+        }
+        let (name, remaining) = if let Some(braced) = after.strip_prefix('{')
+            && let Some(end) = braced.find('}')
+        {
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let len = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..len], &after[len..])
+        };
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            result.push('$');
+            rest = after;
+            continue;
+        }
+        result.push_str(&env::var(name).unwrap_or_default());
+        rest = remaining;
+    }
+    result.push_str(rest);
+    result
+}
+
+#[test]
+fn template_expansion() {
+    let lookup = |placeholder: Placeholder| match placeholder {
+        Placeholder::Internal => "eDP-1".to_string(),
+        Placeholder::Externals => "DP-1 HDMI-A-1".to_string(),
+        Placeholder::FirstExternal => "DP-1".to_string(),
+        Placeholder::Profile => "".to_string(),
+        Placeholder::Mode => "extend".to_string(),
+        Placeholder::Lid => "open".to_string(),
+    };
+    assert_eq!(
+        expand_placeholders("{},disabled", lookup).unwrap(),
+        "eDP-1,disabled"
+    );
+    assert_eq!(
+        expand_placeholders(
+            "{first_external},preferred,auto,1,mirror,{internal}",
+            lookup
+        )
+        .unwrap(),
+        "DP-1,preferred,auto,1,mirror,eDP-1"
+    );
+    assert_eq!(
+        expand_placeholders("{mode} {lid} [{profile}] {externals}", lookup).unwrap(),
+        "extend open [] DP-1 HDMI-A-1"
+    );
+    assert_eq!(
+        expand_placeholders("awk '{print $1}' {", lookup).unwrap(),
+        "awk '{print $1}' {"
+    );
+    assert_eq!(
+        expand_placeholders("{monitor},disabled", lookup),
+        Err(TemplateError::UnknownPlaceholder("monitor".into()))
+    );
+    assert_eq!(
+        expand_placeholders("awk '{{print}' {{{lid}}", lookup).unwrap(),
+        "awk '{print}' {open}"
+    );
+
+    assert_eq!(shell_quote("eDP-1"), "eDP-1");
+    assert_eq!(shell_quote(""), "''");
+    assert_eq!(shell_quote("DP-1 HDMI-A-1"), "'DP-1 HDMI-A-1'");
+    assert_eq!(shell_quote("x'; rm -rf ~; '"), "'x'\\''; rm -rf ~; '\\'''");
+
+    let home = env::var("HOME").unwrap_or_default();
+    assert_eq!(expand_env("$HOME/.config"), format!("{}/.config", home));
+    assert_eq!(expand_env("${HOME}x"), format!("{}x", home));
+    assert_eq!(expand_env("$HYPRDOCK_UNSET_VARIABLE"), "");
+    assert_eq!(expand_env("5$ $1 $$HOME $"), "5$ $1 $HOME $");
+}
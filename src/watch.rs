@@ -0,0 +1,283 @@
+/*
+Copyright © 2023 Fabio Lenherr
+
+This program is free software: you can redistribute it and/or modify
+it under the terms of the GNU Affero General Public License as published by
+the Free Software Foundation, either version 3 of the License, or
+(at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU Affero General Public License for more details.
+
+You should have received a copy of the GNU Affero General Public License
+along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::{
+    ffi::{CString, OsStr, OsString},
+    io, mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::Path,
+    sync::{Arc, Mutex, PoisonError, mpsc::Sender},
+    thread,
+    time::Duration,
+};
+
+use crate::{control::DaemonMessage, profiles::ProfileFormat};
+
+/// Editors often save in several steps, changes within this time are handled together.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+const WATCH_MASK: u32 =
+    libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_MOVED_FROM | libc::IN_DELETE;
+const EVENT_HEADER: usize = mem::size_of::<libc::inotify_event>();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigChange {
+    /// `hyprdock.toml` was written, replaced or removed.
+    Config,
+    /// A profile in `monitor_configs/` was written or removed, by its key.
+    Profile(String),
+}
+
+/// Watches the directories of the config file and the profiles with inotify.
+/// The config directory is watched rather than the file itself, since editors
+/// replace the file on save, which would end a watch on the file.
+pub struct ConfigWatcher {
+    fd: OwnedFd,
+    config_dir: i32,
+    config_name: OsString,
+    /// Changed by the daemon if `monitor_config_path` changes on reload.
+    profile_dir: Mutex<Option<i32>>,
+}
+
+impl ConfigWatcher {
+    pub fn new(config_file: &Path, profile_dir: &Path) -> io::Result<Self> {
+        // SAFETY: inotify_init1 returns a new fd that is owned right away
+        let fd = unsafe {
+            let fd = libc::inotify_init1(libc::IN_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            OwnedFd::from_raw_fd(fd)
+        };
+        let config_name = config_file
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "config is not a file"))?
+            .to_os_string();
+        let config_dir = add_watch(&fd, config_file.parent().unwrap_or(Path::new(".")))?;
+        let profile_dir = add_watch(&fd, profile_dir)
+            .map_err(|err| println!("Could not watch {}: {}", profile_dir.display(), err))
+            .ok();
+        Ok(Self {
+            fd,
+            config_dir,
+            config_name,
+            profile_dir: Mutex::new(profile_dir),
+        })
+    }
+
+    /// Watches a different profile directory instead of the current one.
+    pub fn watch_profiles(&self, dir: &Path) -> io::Result<()> {
+        let mut profile_dir = self
+            .profile_dir
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(wd) = profile_dir.take()
+            && wd != self.config_dir
+        {
+            // SAFETY: wd was returned by inotify_add_watch for this fd
+            unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd) };
+        }
+        *profile_dir = Some(add_watch(&self.fd, dir)?);
+        Ok(())
+    }
+
+    /// Blocks until something changed, each change is reported once.
+    pub fn wait(&self) -> io::Result<Vec<ConfigChange>> {
+        let mut changes = Vec::new();
+        self.read_changes(&mut changes)?;
+        while changes.is_empty() || self.readable_within(DEBOUNCE)? {
+            self.read_changes(&mut changes)?;
+        }
+        Ok(changes)
+    }
+
+    fn read_changes(&self, changes: &mut Vec<ConfigChange>) -> io::Result<()> {
+        let mut buf = [0u8; 4096];
+        let n = loop {
+            // SAFETY: read writes at most buf.len() bytes into buf
+            let n = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n >= 0 {
+                break n as usize;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        };
+        for (wd, mask, name) in parse_events(&buf[..n]) {
+            let change = if mask & libc::IN_Q_OVERFLOW != 0 {
+                // events were lost, the config is the one worth re-reading
+                Some(ConfigChange::Config)
+            } else {
+                self.change(wd, &name)
+            };
+            if let Some(change) = change
+                && !changes.contains(&change)
+            {
+                changes.push(change);
+            }
+        }
+        Ok(())
+    }
+
+    fn change(&self, wd: i32, name: &OsStr) -> Option<ConfigChange> {
+        if wd == self.config_dir && name == self.config_name {
+            return Some(ConfigChange::Config);
+        }
+        if Some(wd)
+            != *self
+                .profile_dir
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+        {
+            return None;
+        }
+        // hidden files like the profile index are written by hyprdock itself
+        let path = Path::new(name);
+        let key = path.file_stem()?.to_str()?;
+        if key.starts_with('.') {
+            return None;
+        }
+        ProfileFormat::from_name(path.extension()?.to_str()?)?;
+        Some(ConfigChange::Profile(key.to_string()))
+    }
+
+    fn readable_within(&self, timeout: Duration) -> io::Result<bool> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: pollfd is a single valid entry
+        let res = unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) };
+        if res < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(false);
+            }
+            return Err(err);
+        }
+        Ok(res > 0)
+    }
+}
+
+fn add_watch(fd: &OwnedFd, dir: &Path) -> io::Result<i32> {
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    // SAFETY: path is a valid nul terminated string
+    let wd = unsafe { libc::inotify_add_watch(fd.as_raw_fd(), path.as_ptr(), WATCH_MASK) };
+    if wd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(wd)
+}
+
+/// Splits a buffer read from inotify into watch descriptor, mask and file name.
+fn parse_events(buf: &[u8]) -> Vec<(i32, u32, OsString)> {
+    let field = |offset: usize| {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&buf[offset..offset + 4]);
+        bytes
+    };
+    let mut events = Vec::new();
+    let mut offset = 0;
+    while offset + EVENT_HEADER <= buf.len() {
+        let wd = i32::from_ne_bytes(field(offset));
+        let mask = u32::from_ne_bytes(field(offset + 4));
+        let len = u32::from_ne_bytes(field(offset + 12)) as usize;
+        let name_end = (offset + EVENT_HEADER + len).min(buf.len());
+        // the name is padded with nul bytes
+        let name = buf[offset + EVENT_HEADER..name_end]
+            .split(|byte| *byte == 0)
+            .next()
+            .unwrap_or_default();
+        events.push((wd, mask, OsStr::from_bytes(name).to_os_string()));
+        offset += EVENT_HEADER + len;
+    }
+    events
+}
+
+/// Passes changes to the daemon until it stops.
+pub fn watch_config(watcher: Arc<ConfigWatcher>, sender: Sender<DaemonMessage>) {
+    thread::spawn(move || {
+        loop {
+            let changes = match watcher.wait() {
+                Ok(changes) => changes,
+                Err(err) => {
+                    println!("Stopped watching the config: {}", err);
+                    return;
+                }
+            };
+            for change in changes {
+                if sender.send(DaemonMessage::ConfigChanged(change)).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn config_watcher_changes() {
+    use std::fs;
+    let root = std::env::temp_dir().join(format!("hyprdock-watch-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let profiles = root.join("monitor_configs");
+    fs::create_dir_all(&profiles).unwrap();
+    let config = root.join("hyprdock.toml");
+    fs::write(&config, "").unwrap();
+    let watcher = ConfigWatcher::new(&config, &profiles).unwrap();
+
+    fs::write(&config, "confirm_timeout = 0\n").unwrap();
+    fs::write(&config, "confirm_timeout = 5\n").unwrap();
+    fs::write(root.join("notes.txt"), "").unwrap();
+    fs::write(profiles.join("desk.toml"), "").unwrap();
+    fs::write(profiles.join(".index.json"), "{}").unwrap();
+    assert_eq!(
+        watcher.wait().unwrap(),
+        vec![ConfigChange::Config, ConfigChange::Profile("desk".into())]
+    );
+
+    // editors write a temporary file and rename it over the config
+    fs::write(root.join("hyprdock.toml.tmp"), "").unwrap();
+    fs::rename(root.join("hyprdock.toml.tmp"), &config).unwrap();
+    fs::remove_file(profiles.join("desk.toml")).unwrap();
+    assert_eq!(
+        watcher.wait().unwrap(),
+        vec![ConfigChange::Config, ConfigChange::Profile("desk".into())]
+    );
+
+    // monitor_config_path changed on reload
+    let moved = root.join("profiles");
+    fs::create_dir_all(&moved).unwrap();
+    watcher.watch_profiles(&moved).unwrap();
+    fs::write(profiles.join("old.toml"), "").unwrap();
+    fs::write(moved.join("new.toml"), "").unwrap();
+    assert_eq!(
+        watcher.wait().unwrap(),
+        vec![ConfigChange::Profile("new".into())]
+    );
+    let _ = fs::remove_dir_all(&root);
+}